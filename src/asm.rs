@@ -0,0 +1,111 @@
+/// The x86 one-byte NOP, used to pad an encoding that is shorter than the instruction
+/// it replaces.
+const NOP: u8 = 0x90;
+
+/// Fits `encoding` into the `len` bytes of the instruction it replaces, padding it with
+/// NOPs. Returns `None` if the encoding is longer than the instruction.
+pub fn pad_to_fit(mut encoding: Vec<u8>, len: usize) -> Option<Vec<u8>> {
+    if encoding.len() > len {
+        return None;
+    }
+    encoding.resize(len, NOP);
+    Some(encoding)
+}
+
+/// Assembles a single x86-64 instruction in Intel syntax, as if it were placed at
+/// `address`.
+#[cfg(feature = "keystone")]
+pub fn assemble(text: &str, address: u64) -> Result<Vec<u8>, String> {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+    use std::slice;
+
+    let text = CString::new(text).map_err(|_| "instruction contains a NUL byte".to_string())?;
+    let error = |err| {
+        unsafe { CStr::from_ptr(ks::ks_strerror(err)) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    let mut engine = ptr::null_mut();
+    let err = unsafe { ks::ks_open(ks::KS_ARCH_X86, ks::KS_MODE_64, &mut engine) };
+    if err != ks::KS_ERR_OK {
+        return Err(error(err));
+    }
+    let mut encoding = ptr::null_mut();
+    let mut size = 0;
+    let mut n_statements = 0;
+    let result = unsafe {
+        ks::ks_asm(
+            engine,
+            text.as_ptr(),
+            address,
+            &mut encoding,
+            &mut size,
+            &mut n_statements,
+        )
+    };
+    let bytes = if result == 0 {
+        let bytes = unsafe { slice::from_raw_parts(encoding, size) }.to_vec();
+        unsafe { ks::ks_free(encoding) };
+        Ok(bytes)
+    } else {
+        Err(error(unsafe { ks::ks_errno(engine) }))
+    };
+    unsafe { ks::ks_close(engine) };
+    match bytes {
+        Ok(bytes) if bytes.is_empty() => Err("no instruction".to_string()),
+        bytes => bytes,
+    }
+}
+
+/// The parts of the keystone C API that `assemble` needs.
+#[cfg(feature = "keystone")]
+mod ks {
+    use std::os::raw::{c_char, c_int, c_uchar, c_uint};
+
+    pub enum Engine {}
+
+    pub const KS_ARCH_X86: c_uint = 4;
+    pub const KS_MODE_64: c_int = 1 << 3;
+    pub const KS_ERR_OK: c_uint = 0;
+
+    #[link(name = "keystone")]
+    extern "C" {
+        pub fn ks_open(arch: c_uint, mode: c_int, engine: *mut *mut Engine) -> c_uint;
+        pub fn ks_close(engine: *mut Engine) -> c_uint;
+        pub fn ks_errno(engine: *mut Engine) -> c_uint;
+        pub fn ks_strerror(err: c_uint) -> *const c_char;
+        pub fn ks_asm(
+            engine: *mut Engine,
+            string: *const c_char,
+            address: u64,
+            encoding: *mut *mut c_uchar,
+            encoding_size: *mut usize,
+            stat_count: *mut usize,
+        ) -> c_int;
+        pub fn ks_free(p: *mut c_uchar);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shorter_encoding_is_padded_with_nops() {
+        assert_eq!(
+            pad_to_fit(vec![0x31, 0xc0], 5),
+            Some(vec![0x31, 0xc0, NOP, NOP, NOP])
+        );
+    }
+
+    #[test]
+    fn encoding_of_the_same_length_is_unchanged() {
+        assert_eq!(pad_to_fit(vec![0xc3], 1), Some(vec![0xc3]));
+    }
+
+    #[test]
+    fn longer_encoding_does_not_fit() {
+        assert_eq!(pad_to_fit(vec![0x48, 0x31, 0xc0], 2), None);
+    }
+}
@@ -1,9 +1,12 @@
 use std::char;
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::num::FpCategory;
 use std::ops::Range;
 use std::{cmp, fmt};
 
+use crate::charset::Charset;
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Format {
     Hex,
@@ -12,20 +15,23 @@ pub enum Format {
     Oct,
     Bin,
     Char,
+    Float,
+    /// A GUID in its canonical form, only shown for 16 byte cells.
+    Guid,
 }
 
 impl Format {
     pub const fn cols_per_byte(&self) -> usize {
         match &self {
-            Format::Hex | Format::Char => 1,
-            Format::UDec | Format::SDec | Format::Oct => 2,
+            Format::Hex | Format::Char | Format::Guid => 1,
+            Format::UDec | Format::SDec | Format::Oct | Format::Float => 2,
             Format::Bin => 4,
         }
     }
 
     pub const fn cycle(&self, rev: bool) -> Self {
         match self {
-            Format::Hex if rev => Format::Char,
+            Format::Hex if rev => Format::Float,
             Format::Hex => Format::UDec,
             Format::UDec if rev => Format::Hex,
             Format::UDec => Format::SDec,
@@ -36,14 +42,31 @@ impl Format {
             Format::Bin if rev => Format::Oct,
             Format::Bin => Format::Char,
             Format::Char if rev => Format::Bin,
-            Format::Char => Format::Hex,
+            Format::Char => Format::Float,
+            Format::Float if rev => Format::Char,
+            Format::Float => Format::Hex,
+            Format::Guid => Format::Hex, // only set explicitly, as it needs a 16 byte cell
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "hex" | "x" => Some(Format::Hex),
+            "udec" | "ude" | "u" => Some(Format::UDec),
+            "sdec" | "sde" | "d" => Some(Format::SDec),
+            "oct" | "o" => Some(Format::Oct),
+            "bin" | "t" => Some(Format::Bin),
+            "char" | "c" => Some(Format::Char),
+            "float" => Some(Format::Float),
+            "guid" | "uuid" => Some(Format::Guid),
+            _ => None,
         }
     }
 
     pub const fn chars_per_byte(&self) -> usize {
         match &self {
-            Format::Hex => 2,
-            Format::UDec | Format::SDec => 3,
+            Format::Hex | Format::Guid => 2,
+            Format::UDec | Format::SDec | Format::Float => 3,
             Format::Oct => 3,
             Format::Bin => 8,
             Format::Char => 1,
@@ -52,8 +75,8 @@ impl Format {
 
     pub const fn radix(&self) -> usize {
         match &self {
-            Format::Hex => 16,
-            Format::UDec | Format::SDec => 10,
+            Format::Hex | Format::Guid => 16,
+            Format::UDec | Format::SDec | Format::Float => 10,
             Format::Oct => 8,
             Format::Bin => 2,
             Format::Char => 256,
@@ -67,6 +90,7 @@ impl Format {
             Format::Oct => c.to_digit(8),
             Format::Bin => c.to_digit(2),
             Format::Char => Some(c as u32),
+            Format::Float | Format::Guid => None,
         }
         .map(|x| x as u8)
     }
@@ -96,6 +120,41 @@ impl Width {
         }
     }
 
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "byte" | "byte8" | "b" => Some(Width::Byte8),
+            "hword" | "hword16" | "h" => Some(Width::HWord16),
+            "word" | "word32" | "w" => Some(Width::Word32),
+            "dword" | "dword64" => Some(Width::DWord64),
+            "qword" | "qword128" | "q" => Some(Width::QWord128),
+            _ => None,
+        }
+    }
+
+    pub const fn from_n_bytes(n: usize) -> Option<Self> {
+        match n {
+            1 => Some(Width::Byte8),
+            2 => Some(Width::HWord16),
+            4 => Some(Width::Word32),
+            8 => Some(Width::DWord64),
+            16 => Some(Width::QWord128),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of decimal digits of the largest unsigned (or, if `signed`,
+    /// the most negative signed) value of this width.
+    pub const fn max_decimal_digits(&self, signed: bool) -> usize {
+        match self {
+            Width::Byte8 => 3,
+            Width::HWord16 => 5,
+            Width::Word32 => 10,
+            Width::DWord64 if signed => 19,
+            Width::DWord64 => 20,
+            Width::QWord128 => 39,
+        }
+    }
+
     pub const fn align(&self, n: usize) -> usize {
         let shift = match &self {
             Width::Byte8 => 0,
@@ -135,6 +194,14 @@ pub enum ByteOrder {
 }
 
 impl ByteOrder {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "big" | "be" => Some(ByteOrder::BigEndian),
+            "little" | "le" => Some(ByteOrder::LittleEndian),
+            _ => None,
+        }
+    }
+
     pub const fn toggle(&self) -> Self {
         match self {
             ByteOrder::LittleEndian => ByteOrder::BigEndian,
@@ -165,6 +232,22 @@ impl Cell {
         Self::new(offset, Format::Hex, Width::Byte8, ByteOrder::LittleEndian)
     }
 
+    /// Applies width, format and byte order names given in any order, e.g. `w udec big`,
+    /// separated by spaces or commas.
+    pub fn with_names(mut self, names: &str) -> Option<Self> {
+        let names = names.split(|c: char| c == ',' || c.is_whitespace());
+        for name in names.filter(|name| !name.is_empty()) {
+            if let Some(width) = Width::from_name(name) {
+                self.width = width;
+            } else if let Some(format) = Format::from_name(name) {
+                self.format = format;
+            } else {
+                self.byte_order = ByteOrder::from_name(name)?;
+            }
+        }
+        Some(self)
+    }
+
     pub const fn n_bytes(&self) -> usize {
         self.width.n_bytes()
     }
@@ -204,8 +287,23 @@ impl Cell {
         }
     }
 
+    /// Encodes `value` in the width and byte order of the cell. Higher bits are dropped.
+    pub fn encode_value(&self, value: u128) -> Vec<u8> {
+        let mut bytes = value.to_le_bytes()[..self.n_bytes()].to_vec();
+        if self.byte_order == ByteOrder::BigEndian {
+            bytes.reverse();
+        }
+        bytes
+    }
+
     pub const fn format(&self, value: u128) -> CellValue {
-        CellValue { cell: *self, value }
+        CellValue {
+            cell: *self,
+            value,
+            separators: false,
+            fixed_width: false,
+            charset: Charset::Ascii,
+        }
     }
 
     pub const fn supports_cursor(&self) -> bool {
@@ -219,31 +317,182 @@ impl Cell {
 pub struct CellValue {
     cell: Cell,
     value: u128,
+    separators: bool,
+    fixed_width: bool,
+    charset: Charset,
+}
+
+/// Inserts a comma between each group of three digits, e.g. `-1048576` -> `-1,048,576`.
+fn group_thousands(digits: &str) -> String {
+    let (sign, digits) = match digits.strip_prefix('-') {
+        Some(digits) => ("-", digits),
+        None => ("", digits),
+    };
+    let mut grouped = String::from(sign);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped
+}
+
+/// Decodes an IEEE 754 half precision float, which is represented exactly by an `f32`.
+/// Also returns the category of the half, as its subnormals are normal `f32`s.
+fn decode_f16(bits: u16) -> (f32, FpCategory) {
+    let sign = if bits >> 15 == 1 { -1.0 } else { 1.0 };
+    let exp = i32::from((bits >> 10) & 0x1f);
+    let frac = f32::from(bits & 0x3ff);
+    match exp {
+        0 if frac == 0.0 => (sign * 0.0, FpCategory::Zero),
+        0 => (sign * frac * 2f32.powi(-24), FpCategory::Subnormal),
+        0x1f if frac == 0.0 => (sign * f32::INFINITY, FpCategory::Infinite),
+        0x1f => (f32::NAN, FpCategory::Nan),
+        _ => (
+            sign * (1.0 + frac / 1024.0) * 2f32.powi(exp - 15),
+            FpCategory::Normal,
+        ),
+    }
+}
+
+/// Formats a float to fit in `max_len` characters. Non-finite values are spelled out, and
+/// values that are too long in plain notation use scientific notation with reduced precision.
+fn format_float<F>(x: F, category: FpCategory, negative: bool, max_len: usize) -> String
+where
+    F: fmt::Display + fmt::LowerExp,
+{
+    match category {
+        FpCategory::Nan => return "NaN".to_string(),
+        FpCategory::Infinite => return if negative { "-Inf" } else { "+Inf" }.to_string(),
+        FpCategory::Subnormal => {} // the plain form would be a long run of zeros
+        FpCategory::Zero | FpCategory::Normal => {
+            let plain = x.to_string();
+            if plain.len() <= max_len {
+                return plain;
+            }
+        }
+    }
+    let sci = format!("{:e}", x);
+    if sci.len() <= max_len {
+        return sci;
+    }
+    (0..17)
+        .rev()
+        .map(|precision| format!("{:.*e}", precision, x))
+        .find(|s| s.len() <= max_len)
+        .unwrap_or(sci)
+}
+
+/// Formats the bytes of a GUID as `xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`. The first three
+/// groups are stored little-endian, the last two as a plain byte sequence.
+fn format_guid(bytes: [u8; 16]) -> String {
+    let hex = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<String>()
+    };
+    format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
+        u16::from_le_bytes(bytes[4..6].try_into().unwrap()),
+        u16::from_le_bytes(bytes[6..8].try_into().unwrap()),
+        hex(&bytes[8..10]),
+        hex(&bytes[10..16])
+    )
 }
 
 impl fmt::Display for CellValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let CellValue { cell, value } = &self;
+        let CellValue { cell, value, .. } = &self;
         let cell_width = f.width().unwrap_or_else(|| cell.n_cols() * 3 - 1);
         let w = cmp::min(cell.n_chars(), cell_width);
 
         match self.cell.format {
             Format::Hex => write!(f, "{1:2$}{:03$x}", value, "", cell_width - w, w),
-            Format::UDec => write!(f, "{:>1$}", value, cell_width),
-            Format::SDec => write!(f, "{:>1$}", *value as i128, cell_width),
+            Format::UDec | Format::SDec => {
+                let signed = cell.format == Format::SDec;
+                let digits = match (signed, self.fixed_width) {
+                    (true, true) => {
+                        let value = *value as i128;
+                        let sign = if value < 0 { '-' } else { ' ' };
+                        let n = cell.width.max_decimal_digits(true);
+                        format!("{}{:02$}", sign, value.unsigned_abs(), n)
+                    }
+                    (true, false) => (*value as i128).to_string(),
+                    (false, true) => {
+                        format!("{:01$}", value, cell.width.max_decimal_digits(false))
+                    }
+                    (false, false) => value.to_string(),
+                };
+                let grouped = if self.separators {
+                    Some(group_thousands(&digits)).filter(|g| g.len() <= cell_width)
+                } else {
+                    None
+                };
+                write!(f, "{:>1$}", grouped.unwrap_or(digits), cell_width)
+            }
             Format::Oct => write!(f, "{1:2$}{:03$o}", value, "", cell_width - w, w),
             Format::Bin => write!(f, "{1:2$}{:03$b}", value, "", cell_width - w, w),
             Format::Char => {
-                let value_char = self.value_to_char().unwrap_or('.');
+                let value_char = self.decode_char().unwrap_or('.');
                 write!(f, "{:>1$}", value_char, cell_width)
             }
+            Format::Float => {
+                let float = match cell.width {
+                    Width::HWord16 => {
+                        let (x, category) = decode_f16(*value as u16);
+                        format_float(x, category, x.is_sign_negative(), cell_width)
+                    }
+                    Width::Word32 => {
+                        let x = f32::from_bits(*value as u32);
+                        format_float(x, x.classify(), x.is_sign_negative(), cell_width)
+                    }
+                    Width::DWord64 => {
+                        let x = f64::from_bits(*value as u64);
+                        format_float(x, x.classify(), x.is_sign_negative(), cell_width)
+                    }
+                    _ => "-".to_string(), // no float type of this width
+                };
+                write!(f, "{:>1$}", float, cell_width)
+            }
+            Format::Guid => {
+                let guid = match cell.width {
+                    Width::QWord128 => format_guid(match cell.byte_order {
+                        ByteOrder::LittleEndian => value.to_le_bytes(),
+                        ByteOrder::BigEndian => value.to_be_bytes(),
+                    }),
+                    _ => "-".to_string(),
+                };
+                write!(f, "{:>1$}", guid, cell_width)
+            }
         }
     }
 }
 
 impl CellValue {
+    /// Enables thousands separators for decimal formats. They are left out again
+    /// if the grouped value does not fit the cell.
+    pub const fn with_separators(self, separators: bool) -> Self {
+        CellValue { separators, ..self }
+    }
+
+    pub const fn with_charset(self, charset: Charset) -> Self {
+        CellValue { charset, ..self }
+    }
+
+    /// Pads decimal values with zeros to the number of digits the largest value of the
+    /// cell width needs, so that they line up regardless of magnitude.
+    pub const fn with_fixed_width(self, fixed_width: bool) -> Self {
+        CellValue {
+            fixed_width,
+            ..self
+        }
+    }
+
     pub fn split(&self, offset: usize) -> (Option<CellValue>, CellValue, Option<CellValue>) {
-        let CellValue { cell, value } = *self;
+        let CellValue { cell, value, .. } = *self;
         let w = cell.n_chars();
         let r = cell.format.radix() as u128;
         let x = r.trailing_zeros() as usize; // log2 (HEX: 4, OCT: 3, BIN: 1)
@@ -251,19 +500,15 @@ impl CellValue {
 
         let prefix = if offset > 0 {
             let value = self.value >> (s + x);
-            Some(CellValue { cell, value })
+            Some(cell.format(value))
         } else {
             None
         };
 
-        let cursor = {
-            let value = (value >> s) & (r - 1);
-            CellValue { cell, value }
-        };
+        let cursor = cell.format((value >> s) & (r - 1));
 
         let suffix = if w - offset - 1 > 0 {
-            let value = value & ((1 << s) - 1);
-            Some(CellValue { cell, value })
+            Some(cell.format(value & ((1 << s) - 1)))
         } else {
             None
         };
@@ -279,6 +524,17 @@ impl CellValue {
         self.value == 0
     }
 
+    /// Decodes a single byte value in the charset of the cell. Wider values are only
+    /// shown as ASCII.
+    fn decode_char(&self) -> Option<char> {
+        match self.charset {
+            Charset::Ascii => self.value_to_char(),
+            charset => u8::try_from(self.value)
+                .ok()
+                .and_then(|b| charset.decode(b)),
+        }
+    }
+
     fn value_to_char(&self) -> Option<char> {
         let c = char::from_u32(self.value as u32)?;
         if c.is_ascii() && !c.is_ascii_control() {
@@ -292,6 +548,10 @@ impl CellValue {
 pub struct SparseCells {
     map: HashMap<usize, Cell>,
     len: usize,
+    default: Cell,
+    /// A second default cell, shown instead of `default` while `alt_active` is set.
+    alt: Option<Cell>,
+    alt_active: bool,
 }
 
 impl SparseCells {
@@ -299,25 +559,150 @@ impl SparseCells {
         SparseCells {
             map: HashMap::default(),
             len,
+            default: Cell::new_hex(0),
+            alt: None,
+            alt_active: false,
         }
     }
 
+    /// Returns the cell at `index` if it has not been annotated.
+    ///
+    /// A trailing group of bytes too short for the default width, or one that overlaps an
+    /// annotated cell of another width, is shown as single bytes.
+    fn default_cell(&self, index: usize) -> Cell {
+        let mut cell = Cell {
+            offset: index,
+            ..self.default()
+        };
+        let group = cell.base_offset()..cell.base_offset() + cell.n_bytes();
+        let overlaps_custom = |i| self.map.get(&i).is_some_and(|c| c.width != cell.width);
+        if group.end > self.len || group.clone().any(overlaps_custom) {
+            cell.width = Width::Byte8;
+        }
+        cell
+    }
+
+    /// Sets the format, width and byte order of all cells that have not been annotated,
+    /// and switches back from the alternate default cell.
+    pub fn set_default(&mut self, cell: Cell) {
+        self.default = cell;
+        self.alt_active = false;
+    }
+
+    /// Returns the cell used for cells that have not been annotated, which is the
+    /// alternate default cell while it is active.
+    pub fn default(&self) -> Cell {
+        match self.alt {
+            Some(alt) if self.alt_active => alt,
+            _ => self.default,
+        }
+    }
+
+    /// Sets the alternate default cell and makes it active.
+    pub fn set_alt(&mut self, cell: Cell) {
+        self.alt = Some(cell);
+        self.alt_active = true;
+    }
+
+    /// Switches between the default and the alternate default cell. Returns false if
+    /// there is no alternate default cell.
+    pub fn toggle_alt(&mut self) -> bool {
+        self.alt_active = !self.alt_active && self.alt.is_some();
+        self.alt.is_some()
+    }
+
     pub fn get(&self, index: usize) -> Cell {
         assert!(index < self.len);
         self.map
             .get(&index)
             .cloned()
-            .unwrap_or_else(|| Cell::new_hex(index))
+            .unwrap_or_else(|| self.default_cell(index))
     }
 
     pub fn get_mut(&mut self, index: usize) -> &mut Cell {
         assert!(index < self.len);
-        self.map
-            .entry(index)
-            .or_insert_with(|| Cell::new_hex(index))
+        let default = self.default_cell(index);
+        self.map.entry(index).or_insert(default)
     }
 
     pub fn len(&self) -> usize {
         self.len
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether the cell at `index` differs from the default cell.
+    pub fn is_custom(&self, index: usize) -> bool {
+        self.map
+            .get(&index)
+            .is_some_and(|cell| *cell != self.default_cell(index))
+    }
+
+    /// Returns the first index at or after `start` whose cell is not custom,
+    /// or `len` if the custom cells extend to the end.
+    pub fn end_of_custom_run(&self, start: usize) -> usize {
+        (start..self.len)
+            .find(|&i| !self.is_custom(i))
+            .unwrap_or(self.len)
+    }
+
+    /// Returns the annotated cells sorted by offset, one per cell rather than per byte.
+    pub fn custom_cells(&self) -> Vec<Cell> {
+        let mut offsets: Vec<usize> = self
+            .map
+            .values()
+            .map(|cell| cell.base_offset())
+            .filter(|&offset| self.is_custom(offset))
+            .collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets.into_iter().map(|offset| self.get(offset)).collect()
+    }
+
+    /// Copies the annotated cells that lie within `template` to `count - 1` further
+    /// records, each `stride` bytes after the previous one. Annotations in the way are
+    /// replaced, and records past the end are left out. Returns the number of records
+    /// written.
+    pub fn replicate(&mut self, template: Range<usize>, stride: usize, count: usize) -> usize {
+        let cells: Vec<Cell> = self
+            .custom_cells()
+            .into_iter()
+            .filter(|cell| template.start <= cell.offset && cell.byte_range().end <= template.end)
+            .collect();
+        let mut n_records = 0;
+        for k in 1..count {
+            let shift = match k.checked_mul(stride) {
+                Some(shift) => shift,
+                None => break,
+            };
+            match template.end.checked_add(shift) {
+                Some(end) if end <= self.len => {}
+                _ => break,
+            }
+            let start = template.start + shift;
+            for i in start..start + template.len() {
+                if let Some(cell) = self.map.get(&i).copied() {
+                    let group = cell.base_offset()..cell.base_offset() + cell.n_bytes();
+                    for j in group {
+                        self.map.remove(&j);
+                    }
+                }
+            }
+            for cell in &cells {
+                for i in cell.byte_range() {
+                    let offset = i + shift;
+                    self.map.insert(offset, Cell { offset, ..*cell });
+                }
+            }
+            n_records += 1;
+        }
+        n_records
+    }
+
+    pub fn resize(&mut self, len: usize) {
+        self.map.retain(|&i, _| i < len);
+        self.len = len;
+    }
 }
@@ -0,0 +1,72 @@
+/// The character encodings available to the char format.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Charset {
+    Ascii,
+    Latin1,
+    Cp437,
+    Ebcdic,
+}
+
+/// The glyphs of CP437 bytes 0x01 to 0x1f.
+const CP437_LOW: &str = "☺☻♥♦♣♠•◘○◙♂♀♪♫☼►◄↕‼¶§▬↨↑↓→←∟↔▲▼";
+
+/// The glyphs of CP437 bytes 0x80 to 0xff.
+const CP437_HIGH: &str = concat!(
+    "ÇüéâäàåçêëèïîìÄÅ",
+    "ÉæÆôöòûùÿÖÜ¢£¥₧ƒ",
+    "áíóúñÑªº¿⌐¬½¼¡«»",
+    "░▒▓│┤╡╢╖╕╣║╗╝╜╛┐",
+    "└┴┬├─┼╞╟╚╔╩╦╠═╬╧",
+    "╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀",
+    "αßΓπΣσµτΦΘΩδ∞φε∩",
+    "≡±≥≤⌠⌡÷≈°∙·√ⁿ²■ ",
+);
+
+/// The characters of EBCDIC (code page 37) bytes 0x40 to 0xff, where `\0` marks
+/// control characters. Bytes below 0x40 are all control characters.
+const EBCDIC: &str = concat!(
+    "  âäàáãåçñ¢.<(+|",
+    "&éêëèíîïìß!$*);¬",
+    "-/ÂÄÀÁÃÅÇÑ¦,%_>?",
+    "øÉÊËÈÍÎÏÌ`:#@'=\"",
+    "Øabcdefghi«»ðýþ±",
+    "°jklmnopqrªºæ¸Æ¤",
+    "µ~stuvwxyz¡¿ÐÝÞ®",
+    "^£¥·©§¶¼½¾[]¯¨´×",
+    "{ABCDEFGHI\0ôöòóõ",
+    "}JKLMNOPQR¹ûüùúÿ",
+    "\\÷STUVWXYZ²ÔÖÒÓÕ",
+    "0123456789³ÛÜÙÚ\0",
+);
+
+impl Charset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "ascii" => Some(Charset::Ascii),
+            "latin1" => Some(Charset::Latin1),
+            "cp437" => Some(Charset::Cp437),
+            "ebcdic" => Some(Charset::Ebcdic),
+            _ => None,
+        }
+    }
+
+    /// Returns the printable character `b` stands for, if any.
+    pub fn decode(&self, b: u8) -> Option<char> {
+        let c = match self {
+            Charset::Ascii => Some(b as char).filter(|c| c.is_ascii()),
+            Charset::Latin1 => Some(b as char).filter(|&c| c != '\u{ad}'), // soft hyphen
+            Charset::Cp437 => match b {
+                0x00 => None,
+                0x01..=0x1f => CP437_LOW.chars().nth(b as usize - 0x01),
+                0x7f => Some('⌂'),
+                0x80..=0xff => CP437_HIGH.chars().nth(b as usize - 0x80),
+                _ => Some(b as char),
+            },
+            Charset::Ebcdic => match b {
+                0x00..=0x3f => None,
+                _ => EBCDIC.chars().nth(b as usize - 0x40),
+            },
+        };
+        c.filter(|&c| c != '\0' && !c.is_control())
+    }
+}
@@ -0,0 +1,116 @@
+use std::ops::Range;
+
+use crate::cell::{ByteOrder, Width};
+
+/// The checksums that `:checksum` can compute.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Algorithm {
+    /// CRC-32 as used by zip, gzip and PNG.
+    Crc32,
+    /// The sum of the bytes, modulo 256.
+    Sum8,
+    /// The sum of the bytes, modulo 65536.
+    Sum16,
+    /// The one's complement of the one's complement sum of big endian 16 bit words,
+    /// as in IP, TCP and UDP headers.
+    Internet,
+}
+
+impl Algorithm {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "crc32" => Some(Algorithm::Crc32),
+            "sum8" => Some(Algorithm::Sum8),
+            "sum16" => Some(Algorithm::Sum16),
+            "inet" | "internet" => Some(Algorithm::Internet),
+            _ => None,
+        }
+    }
+
+    /// The width of the field the checksum is stored in.
+    pub const fn width(&self) -> Width {
+        match self {
+            Algorithm::Crc32 => Width::Word32,
+            Algorithm::Sum8 => Width::Byte8,
+            Algorithm::Sum16 | Algorithm::Internet => Width::HWord16,
+        }
+    }
+
+    /// The byte order the checksum must be stored in, or `None` if it follows the field.
+    /// The Internet checksum is summed over big endian words, so it is only valid stored
+    /// big endian.
+    pub const fn byte_order(&self) -> Option<ByteOrder> {
+        match self {
+            Algorithm::Internet => Some(ByteOrder::BigEndian),
+            Algorithm::Crc32 | Algorithm::Sum8 | Algorithm::Sum16 => None,
+        }
+    }
+
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        match self {
+            Algorithm::Crc32 => crc32(data),
+            Algorithm::Sum8 => data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)).into(),
+            Algorithm::Sum16 => data
+                .iter()
+                .fold(0u16, |sum, &b| sum.wrapping_add(b.into()))
+                .into(),
+            Algorithm::Internet => internet_checksum(data).into(),
+        }
+    }
+}
+
+/// A checksum over the bytes in `src`, stored at `dst`.
+#[derive(Debug, Clone)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub src: Range<usize>,
+    pub dst: usize,
+}
+
+impl Checksum {
+    /// The bytes the checksum is stored in.
+    pub fn dst_range(&self) -> Range<usize> {
+        self.dst..self.dst + self.algorithm.width().n_bytes()
+    }
+
+    /// Computes the checksum of `data[src]`. Bytes of the checksum field itself are taken
+    /// as zero, as for a header that contains its own checksum.
+    pub fn compute(&self, data: &[u8]) -> u32 {
+        let mut bytes = data[self.src.clone()].to_vec();
+        let dst = self.dst_range();
+        for (i, b) in self.src.clone().zip(&mut bytes) {
+            if dst.contains(&i) {
+                *b = 0;
+            }
+        }
+        self.algorithm.compute(&bytes)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(i as u32, |c, _| {
+            if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            }
+        });
+    }
+    !data.iter().fold(!0, |crc, &b| {
+        table[((crc ^ u32::from(b)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Computes the checksum of RFC 1071. An odd trailing byte is padded with a zero.
+fn internet_checksum(data: &[u8]) -> u16 {
+    let mut sum: u64 = data
+        .chunks(2)
+        .map(|pair| u64::from(pair[0]) << 8 | u64::from(*pair.get(1).unwrap_or(&0)))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
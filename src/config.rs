@@ -0,0 +1,78 @@
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use crate::util::parse_hex_bytes;
+
+pub const DEFAULT_STATUS_FORMAT: &str =
+    "{mode} ({x}, {y}) {offset} {format} {width} {byte_order} {percent}%";
+
+/// Common x86-64 function prologues: `push rbp; mov rbp, rsp` in both encodings, and
+/// `endbr64`.
+pub const DEFAULT_PROLOGUES: &[&[u8]] = &[
+    &[0x55, 0x48, 0x89, 0xe5],
+    &[0x55, 0x48, 0x8b, 0xec],
+    &[0xf3, 0x0f, 0x1e, 0xfa],
+];
+
+/// Returns the path of a file in the user's bloxberg config directory.
+pub fn path(name: &str) -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_dir.join("bloxberg").join(name))
+}
+
+pub struct Config {
+    pub status_format: String,
+    pub prologues: Vec<Vec<u8>>,
+}
+
+impl Config {
+    /// Loads the settings in `path`, falling back to defaults if it does not exist.
+    ///
+    /// Each line of the file has the form `<key> = <value>`. Empty lines and lines
+    /// starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut config = Config::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(config),
+        };
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg| format!("{}:{}: {}", path.display(), i + 1, msg);
+
+            let mut setting = line.splitn(2, '=');
+            let (key, value) = match (setting.next(), setting.next()) {
+                (Some(key), Some(value)) => (key.trim(), value.trim()),
+                _ => return Err(error("expected \"<key> = <value>\"")),
+            };
+            match key {
+                "status_format" => config.status_format = value.to_string(),
+                "prologues" => {
+                    config.prologues = value
+                        .split(',')
+                        .filter(|pattern| !pattern.trim().is_empty())
+                        .map(parse_hex_bytes)
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| error("expected comma-separated hex byte patterns"))?
+                }
+                _ => return Err(error("unknown setting")),
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            status_format: DEFAULT_STATUS_FORMAT.to_string(),
+            prologues: DEFAULT_PROLOGUES.iter().map(|p| p.to_vec()).collect(),
+        }
+    }
+}
@@ -1,20 +1,68 @@
-use memmap::{MmapMut, MmapOptions};
+use memmap::{Mmap, MmapMut, MmapOptions};
 use std::fs::File;
 use std::io;
-use std::io::Write;
+use std::io::{Seek, SeekFrom, Write};
+use std::mem;
+use std::ops::Range;
+use std::os::unix::fs::FileTypeExt;
 
 pub enum DataStore {
     File(MmapMut, File),
     Anon(MmapMut),
+    ReadOnly(Mmap),
+    Empty,
+    /// A zero-length file, which cannot be mapped but is written to once it has grown.
+    EmptyFile(File),
+    /// A zero-length file opened read-only, which stays empty.
+    EmptyReadOnly,
+}
+
+/// Returns the number of bytes to map for `file`.
+///
+/// Block devices report a length of zero in their metadata, so their size is queried by
+/// seeking to the end. Pipes, sockets and character devices cannot be mapped.
+fn map_len(mut file: &File) -> io::Result<usize> {
+    let metadata = file.metadata()?;
+    let file_type = metadata.file_type();
+    if file_type.is_file() {
+        Ok(metadata.len() as usize)
+    } else if file_type.is_block_device() {
+        let len = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(len as usize)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "not a regular file or block device",
+        ))
+    }
 }
 
 impl DataStore {
     pub fn file(file: File) -> io::Result<Self> {
-        let mmap = unsafe { MmapOptions::new().map_copy(&file)? };
+        let len = map_len(&file)?;
+        if len == 0 {
+            return Ok(DataStore::EmptyFile(file)); // zero-length maps are rejected by mmap
+        }
+        let mmap = unsafe { MmapOptions::new().len(len).map_copy(&file)? };
         Ok(DataStore::File(mmap, file))
     }
 
+    /// Maps `file` without copy-on-write, so that files larger than the available
+    /// memory can be inspected. The data of a read-only store cannot be modified.
+    pub fn read_only(file: File) -> io::Result<Self> {
+        let len = map_len(&file)?;
+        if len == 0 {
+            return Ok(DataStore::EmptyReadOnly);
+        }
+        let mmap = unsafe { MmapOptions::new().len(len).map(&file)? };
+        Ok(DataStore::ReadOnly(mmap))
+    }
+
     pub fn anon(n_bytes: usize) -> io::Result<Self> {
+        if n_bytes == 0 {
+            return Ok(DataStore::Empty);
+        }
         let mmap = MmapOptions::new().len(n_bytes).map_anon()?;
         Ok(DataStore::Anon(mmap))
     }
@@ -23,24 +71,97 @@ impl DataStore {
         match self {
             DataStore::File(mmap, _) => mmap,
             DataStore::Anon(mmap) => mmap,
+            DataStore::ReadOnly(mmap) => mmap,
+            DataStore::Empty | DataStore::EmptyFile(_) | DataStore::EmptyReadOnly => &[],
         }
     }
 
-    pub fn data_mut(&mut self) -> &mut [u8] {
+    pub fn data_mut(&mut self) -> Option<&mut [u8]> {
         match self {
-            DataStore::File(mmap, _) => mmap,
-            DataStore::Anon(mmap) => mmap,
+            DataStore::File(mmap, _) => Some(mmap),
+            DataStore::Anon(mmap) => Some(mmap),
+            DataStore::ReadOnly(..) | DataStore::EmptyReadOnly => None,
+            DataStore::Empty | DataStore::EmptyFile(_) => Some(&mut []),
         }
     }
 
-    pub fn write(&mut self) -> io::Result<()> {
+    pub fn is_read_only(&self) -> bool {
+        matches!(self, DataStore::ReadOnly(..) | DataStore::EmptyReadOnly)
+    }
+
+    /// Appends `n_bytes` zero bytes to the buffer. A grown empty file stays attached to
+    /// the file, which is extended by writing the new bytes.
+    pub fn grow(&mut self, n_bytes: usize) -> io::Result<()> {
+        let old = match self {
+            DataStore::Anon(mmap) => &mmap[..],
+            DataStore::Empty | DataStore::EmptyFile(_) => &[],
+            DataStore::File(..) | DataStore::ReadOnly(..) => {
+                return Err(io::Error::other("cannot grow a file-backed buffer"))
+            }
+            DataStore::EmptyReadOnly => return Err(io::Error::other("buffer is read-only")),
+        };
+        let mut mmap = MmapOptions::new().len(old.len() + n_bytes).map_anon()?;
+        mmap[..old.len()].copy_from_slice(old);
+        *self = match mem::replace(self, DataStore::Empty) {
+            DataStore::EmptyFile(file) => DataStore::File(mmap, file),
+            _ => DataStore::Anon(mmap),
+        };
+        Ok(())
+    }
+
+    /// Returns the file the buffer was loaded from, whose contents may differ from
+    /// the buffer by unsaved edits or by changes made to the file since.
+    pub fn backing_file(&self) -> Option<&File> {
+        match self {
+            DataStore::File(_, file) | DataStore::EmptyFile(file) => Some(file),
+            _ => None,
+        }
+    }
+
+    /// Waits until the written data of the underlying file has reached the disk.
+    pub fn sync(&self) -> io::Result<()> {
+        match self {
+            DataStore::File(_, file) | DataStore::EmptyFile(file) => file.sync_data(),
+            DataStore::ReadOnly(_) | DataStore::EmptyReadOnly => {
+                Err(io::Error::other("buffer is read-only"))
+            }
+            DataStore::Anon(_) | DataStore::Empty => {
+                Err(io::Error::other("buffer is not backed by a file"))
+            }
+        }
+    }
+
+    /// Writes the bytes in `range` back to the underlying file.
+    pub fn write(&mut self, range: Range<usize>) -> io::Result<()> {
         match self {
             DataStore::File(mmap, file) => {
-                file.write_all(mmap)?;
-                file.flush()?;
+                file.seek(SeekFrom::Start(range.start as u64))?;
+                file.write_all(&mmap[range])?;
+                file.flush()
             }
-            _ => {}
+            DataStore::EmptyFile(_) => Ok(()),
+            DataStore::Anon(_)
+            | DataStore::Empty
+            | DataStore::ReadOnly(_)
+            | DataStore::EmptyReadOnly => Err(io::Error::other("buffer is not backed by a file")),
         }
-        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn empty_read_only_file_cannot_grow() {
+        let path = env::temp_dir().join(format!("bloxberg-empty-{}", std::process::id()));
+        File::create(&path).unwrap();
+        let mut data_store = DataStore::read_only(File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(data_store.is_read_only());
+        assert!(data_store.grow(16).is_err());
+        assert!(data_store.is_read_only());
+        assert!(data_store.data_mut().is_none());
     }
 }
@@ -1,17 +1,128 @@
 use crate::util::cmp_range;
+use capstone::arch::x86::X86OperandType;
+use capstone::arch::ArchOperand;
 use capstone::prelude::*;
-use std::convert::TryInto;
+use capstone::{InsnGroupType, Instructions};
+use std::cmp::min;
 use std::ops::Range;
 
+/// The kind of a piece of disassembled text, used to color it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Token {
+    Address,
+    Mnemonic,
+    Register,
+    Immediate,
+    Other,
+}
+
+/// The text of an instruction, split into pieces for coloring.
+pub type Spans = [(Token, String)];
+
 #[derive(Debug)]
 struct Insn {
     byte_range: Range<usize>,
-    asm: String,
+    spans: Vec<(Token, String)>,
+    target: Option<u64>,
+}
+
+/// Returns the target of a direct jump or call, which capstone reports as an immediate.
+fn branch_target(cs: &Capstone, insn: &capstone::Insn) -> Option<u64> {
+    let detail = cs.insn_detail(insn).ok()?;
+    let is_branch = detail.groups().any(|group| {
+        let group = group.0 as u32;
+        group == InsnGroupType::CS_GRP_JUMP || group == InsnGroupType::CS_GRP_CALL
+    });
+    if !is_branch {
+        return None;
+    }
+    detail
+        .arch_detail()
+        .operands()
+        .into_iter()
+        .find_map(|op| match op {
+            ArchOperand::X86Operand(op) => match op.op_type {
+                X86OperandType::Imm(imm) => Some(imm as u64),
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+/// Returns the names of the registers referenced by the operands of `insn`.
+fn operand_regs(cs: &Capstone, insn: &capstone::Insn) -> Vec<String> {
+    let detail = match cs.insn_detail(insn) {
+        Ok(detail) => detail,
+        Err(_) => return vec![],
+    };
+    let mut regs = vec![];
+    for op in detail.arch_detail().operands() {
+        if let ArchOperand::X86Operand(op) = op {
+            match op.op_type {
+                X86OperandType::Reg(reg) => regs.push(reg),
+                X86OperandType::Mem(mem) => regs.extend(&[mem.base(), mem.index()]),
+                _ => {}
+            }
+        }
+    }
+    regs.into_iter()
+        .filter_map(|reg| cs.reg_name(reg))
+        .collect()
+}
+
+/// Splits the operand string into words and separators, classifying each word as
+/// an immediate (starts with a digit), a register (listed in `regs`), or other text.
+fn tokenize_operands(op_str: &str, regs: &[String], spans: &mut Vec<(Token, String)>) {
+    let mut rest = op_str;
+    while let Some(c) = rest.chars().next() {
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let len = if is_word(c) {
+            rest.find(|c| !is_word(c)).unwrap_or(rest.len())
+        } else {
+            c.len_utf8()
+        };
+        let (text, tail) = rest.split_at(len);
+        let token = if c.is_ascii_digit() {
+            Token::Immediate
+        } else if regs.iter().any(|reg| reg == text) {
+            Token::Register
+        } else {
+            Token::Other
+        };
+        spans.push((token, text.to_string()));
+        rest = tail;
+    }
+}
+
+/// Converts the instructions disassembled at `base` plus their file offset.
+fn to_insns(cs: &Capstone, insns: &Instructions<'_>, base: u64) -> Vec<Insn> {
+    insns
+        .iter()
+        .map(|insn| {
+            let addr = insn.address().wrapping_sub(base) as usize;
+            let mut spans = vec![
+                (Token::Address, format!("{:#x}:", insn.address())),
+                (Token::Other, " ".to_string()),
+                (Token::Mnemonic, insn.mnemonic().unwrap_or("").to_string()),
+            ];
+            if let Some(op_str) = insn.op_str().filter(|s| !s.is_empty()) {
+                spans.push((Token::Other, " ".to_string()));
+                tokenize_operands(op_str, &operand_regs(cs, &insn), &mut spans);
+            }
+            Insn {
+                byte_range: addr..addr + insn.bytes().len(),
+                spans,
+                target: branch_target(cs, &insn),
+            }
+        })
+        .collect()
 }
 
 pub struct DisasmView {
     cs: Capstone,
     insns: Vec<Insn>,
+    /// The address at which the start of the buffer is loaded.
+    base: u64,
 }
 
 impl DisasmView {
@@ -23,42 +134,88 @@ impl DisasmView {
             .detail(true)
             .build()
             .unwrap();
-        DisasmView { cs, insns: vec![] }
+        DisasmView {
+            cs,
+            insns: vec![],
+            base: 0,
+        }
     }
 
-    pub fn is_enabled(&self) -> bool {
-        !self.insns.is_empty()
+    pub fn disassemble(&mut self, addr: usize, count: usize, data: &[u8]) {
+        let insns = self
+            .cs
+            .disasm_count(&data[addr..], self.base.wrapping_add(addr as u64), count)
+            .unwrap();
+        self.insns = to_insns(&self.cs, &insns, self.base);
     }
 
-    pub fn disassemble(&mut self, addr: usize, count: usize, data: &[u8]) {
-        self.insns = self
+    /// Disassembles the `len` bytes starting at `addr`, clamped to the end of `data`.
+    pub fn disassemble_bytes(&mut self, addr: usize, len: usize, data: &[u8]) {
+        let end = min(addr.saturating_add(len), data.len());
+        let insns = self
             .cs
-            .disasm_count(&data[addr..], addr as u64, count)
-            .unwrap()
-            .iter()
-            .map(|insn| {
-                let addr = insn.address() as usize;
-                Insn {
-                    byte_range: addr..addr + insn.bytes().len(),
-                    asm: insn.to_string(),
-                }
-            })
-            .collect();
-        eprintln!("{:?}", self.insns);
+            .disasm_all(&data[addr..end], self.base.wrapping_add(addr as u64))
+            .unwrap();
+        self.insns = to_insns(&self.cs, &insns, self.base);
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
     }
 
-    pub fn get(&self, cursor_offset: usize, relative_scroll: isize) -> Option<&str> {
-        if relative_scroll.abs() as usize > self.insns.len() {
-            return None;
+    /// Sets the load address of the buffer and disassembles the current bytes again.
+    pub fn set_base(&mut self, base: u64, data: &[u8]) {
+        self.base = base;
+        if let (Some(first), Some(last)) = (self.insns.first(), self.insns.last()) {
+            let range = first.byte_range.start..last.byte_range.end;
+            self.disassemble_bytes(range.start, range.len(), data);
         }
+    }
 
-        let insn_idx = self
+    pub fn len(&self) -> usize {
+        self.insns.len()
+    }
+
+    /// Returns the index of the instruction containing `offset`.
+    pub fn index_at(&self, offset: usize) -> Option<usize> {
+        self.insns
+            .binary_search_by(|insn| cmp_range(offset, insn.byte_range.clone()).reverse())
+            .ok()
+    }
+
+    /// Returns the target address of the branch instruction at `offset`.
+    /// The outer option is `None` if no instruction contains `offset`.
+    pub fn branch_target(&self, offset: usize) -> Option<Option<u64>> {
+        let idx = self.index_at(offset)?;
+        Some(self.insns[idx].target)
+    }
+
+    /// Returns the index of the instruction containing `offset`, or of the first
+    /// instruction after it.
+    pub fn first_at(&self, offset: usize) -> Option<usize> {
+        let idx = self
             .insns
-            .binary_search_by(|insn| cmp_range(cursor_offset, insn.byte_range.clone()).reverse())
-            .ok()?;
-        let insn_idx = insn_idx as isize + relative_scroll;
-        let insn_idx: usize = insn_idx.try_into().ok()?;
-        let insn: &Insn = self.insns.get(insn_idx)?;
-        return Some(&insn.asm);
+            .partition_point(|insn| insn.byte_range.end <= offset);
+        Some(idx).filter(|&idx| idx < self.insns.len())
+    }
+
+    /// Returns the byte range and the assembly text, without the address, of the
+    /// instruction containing `offset`.
+    #[cfg(feature = "keystone")]
+    pub fn insn_at(&self, offset: usize) -> Option<(Range<usize>, String)> {
+        let insn = &self.insns[self.index_at(offset)?];
+        let text: String = insn
+            .spans
+            .iter()
+            .filter(|(token, _)| *token != Token::Address)
+            .map(|(_, text)| text.as_str())
+            .collect();
+        Some((insn.byte_range.clone(), text.trim().to_string()))
+    }
+
+    /// Returns the byte range and the text of the instruction at `idx`.
+    pub fn get(&self, idx: usize) -> Option<(Range<usize>, &Spans)> {
+        let insn = self.insns.get(idx)?;
+        Some((insn.byte_range.clone(), &insn.spans))
     }
 }
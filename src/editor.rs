@@ -1,18 +1,39 @@
+use std::collections::{BTreeSet, HashMap};
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
 use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::*;
 
-use std::cmp::{max, min};
+use std::cmp::{max, min, Reverse};
 
+#[cfg(feature = "keystone")]
+use crate::asm;
 use crate::cell::*;
+use crate::charset::Charset;
+use crate::checksum::{Algorithm, Checksum};
+use crate::config::DEFAULT_STATUS_FORMAT;
 use crate::data_store::DataStore;
-use crate::disasm::DisasmView;
+use crate::disasm::{DisasmView, Token};
+use crate::elf::Elf;
+use crate::interrupt::Interrupt;
+use crate::keymap::Action;
+use crate::magic;
+use crate::overlay::Overlay;
+use crate::scan;
 use crate::terminal::{Color, Terminal};
-use crate::util::cmp_range;
+use crate::util::{bar, clamp_to_usize, cmp_range, parse_addr, parse_count, parse_hex_bytes};
 use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use termion::event::Key;
 
 const PADDING_TOP: usize = 1;
 const PADDING_BOTTOM: usize = 1;
 const PADDING_LEFT: usize = 2 + 2 * Width::ADDRESS.n_bytes();
+/// The number of screen columns a horizontal scroll step moves, one byte column.
+const H_SCROLL_STEP: usize = 3;
+const RULE: char = '│';
+const RULE_INTERVAL: usize = 8;
 
 #[derive(Debug, Copy, Clone)]
 struct Line {
@@ -55,34 +76,183 @@ impl Line {
         (offset - self.offset) * self.cpb
     }
 
-    fn is_empty(&self) -> bool {
-        self.len == 0
+    /// Returns the column of the cell that is as many bytes into this line as `offset`
+    /// is into `from`, or of the last cell if this line is shorter.
+    fn col_of_same_byte(&self, from: &Line, offset: usize, cells: &SparseCells) -> usize {
+        let byte_idx = offset - from.offset;
+        let offset = self.offset + min(byte_idx, self.len - 1);
+        self.offset_to_col(cells.get(offset).base_offset())
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Viewport {
+    top: usize,
+    height: usize,
+    scroll: usize,
+    cursor_x: usize,
+    cursor_y: usize,
+}
+
+impl Viewport {
+    fn new(top: usize, height: usize) -> Self {
+        Viewport {
+            top,
+            height,
+            scroll: 0,
+            cursor_x: 0,
+            cursor_y: 0,
+        }
+    }
+
+    /// Scrolls so that at least `scrolloff` lines stay visible above and below the cursor,
+    /// without scrolling past the last of `n_lines`.
+    fn scroll_to_cursor(&mut self, scrolloff: usize, n_lines: usize) {
+        let margin = min(scrolloff, self.height.saturating_sub(1) / 2);
+        if self.cursor_y < self.scroll + margin {
+            self.scroll = self.cursor_y.saturating_sub(margin);
+        } else if self.cursor_y + margin >= self.scroll + self.height {
+            let max_scroll = max(n_lines, self.cursor_y + 1).saturating_sub(self.height);
+            self.scroll = min(self.cursor_y + margin + 1 - self.height, max_scroll);
+        }
+    }
+}
+
+/// The lines of the panes that the next draw formats and sends again. The others are
+/// left as they are on the screen.
+#[derive(Debug, Clone, PartialEq)]
+enum Redraw {
+    All,
+    Lines(BTreeSet<usize>),
+}
+
+impl Redraw {
+    fn includes(&self, line_idx: usize) -> bool {
+        match self {
+            Redraw::All => true,
+            Redraw::Lines(lines) => lines.contains(&line_idx),
+        }
+    }
+
+    fn add(&mut self, line_idx: usize) {
+        if let Redraw::Lines(lines) = self {
+            lines.insert(line_idx);
+        }
+    }
+}
+
+/// What the panes are drawn with, apart from the cursor position. While it stays the
+/// same, moving the cursor only changes the line it left and the line it entered.
+#[derive(Debug, PartialEq)]
+struct Frame {
+    /// The viewports of both panes, with the cursor of the current one left out.
+    views: (Viewport, Option<Viewport>),
+    /// The line shown in each row of each pane.
+    rows: Vec<Vec<Option<usize>>>,
+    mode: EditorMode,
+    ascii_focus: bool,
+    selection: Option<Range<usize>>,
+    header_cpb: usize,
+    h_scroll: usize,
+    /// The instruction highlighted in the disasm column, which follows the cursor.
+    insn_at_cursor: Option<usize>,
+    /// The line relative offsets are counted from, if they are shown.
+    relative_to: Option<usize>,
+}
+
+/// The last search, which `n` and `N` repeat.
+#[derive(Debug, Clone)]
+enum Search {
+    /// An aligned value of the width and byte order of the cell.
+    Value(Cell, u128),
+    /// A byte that differs from a pattern tiled across the buffer.
+    Anomaly(Vec<u8>),
+}
+
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum EditorMode {
     Normal,
     Insert,
     Command,
+    Visual,
 }
 
 pub struct Editor<'d, W: Write> {
     data_store: &'d mut DataStore,
     terminal: Terminal<W>,
-    pub height: usize,
+    width: usize,
+    body_height: usize,
     n_cols: usize,
     mode: EditorMode,
-    scroll: usize,
-    cursor_x: usize,
-    cursor_y: usize,
+    view: Viewport,
+    split: Option<Viewport>,
     cursor_offset: usize,
+    anchor: usize,
+    /// The visual selection a command was entered from, which the command applies to.
+    cmd_selection: Option<Range<usize>>,
+    /// Whether insert mode writes typed characters as bytes, whatever the cell format.
+    ascii_focus: bool,
+    /// The characters typed after a backslash in char insert mode.
+    escape: Option<String>,
+    scrolloff: usize,
+    /// Whether moving past either end of the buffer wraps around to the other end.
+    wrap: bool,
+    /// Whether runs of single byte char cells are drawn as contiguous text.
+    compact_char: bool,
+    /// Whether the values of each line are repeated in `dual_format` beside it.
+    dual: bool,
+    dual_format: Format,
+    /// The number of columns the lines are scrolled to the left, right of the offsets.
+    h_scroll: usize,
+    /// The widest line right of the offsets in the last full draw and since.
+    content_width: usize,
+    /// The lines the next draw formats again, besides those the cursor left and entered.
+    redraw: Redraw,
+    /// The frame and cursor line of the last draw of the panes.
+    drawn: Option<(Frame, usize)>,
+    /// Whether the cursor is hidden and vertical motions scroll the view instead.
+    pager: bool,
+    back_stack: Vec<usize>,
+    prev_offset: Option<usize>,
+    count: Option<usize>,
     cells: SparseCells,
     lines: Vec<Line>,
     cmd_buf: String,
+    message: Option<String>,
+    status_format: String,
+    overlay: Option<Overlay>,
+    last_cmd: String,
     pub finished: bool,
-    dirty: bool,
+    dirty: Option<Range<usize>>,
+    journal: Option<File>,
+    original: HashMap<usize, u8>,
+    /// Text labels attached to cells, by the offset of the cell.
+    labels: HashMap<usize, String>,
+    /// Byte ranges that are not written to, even though the buffer is writable.
+    locks: Vec<Range<usize>>,
+    /// Checksums that are recomputed before each write.
+    checksums: Vec<Checksum>,
+    show_modified: bool,
+    separators: bool,
+    fixed_width: bool,
+    relative: bool,
+    col_rules: bool,
+    charset: Charset,
+    ptr_width: Width,
+    last_find: Option<Search>,
+    collapse: bool,
+    expanded: Vec<Range<usize>>,
     disasm_view: DisasmView,
+    elf: Option<Elf>,
+    show_va: bool,
+    pending: Option<(Action, usize)>,
+    recording: Option<(char, Vec<Key>)>,
+    macros: HashMap<char, Vec<Key>>,
+    replaying: bool,
+    style: Option<Cell>,
+    prologues: Vec<Vec<u8>>,
+    clipboard: Vec<u8>,
+    interrupt: Interrupt,
 }
 
 impl<'d, W: Write> Editor<'d, W> {
@@ -101,36 +271,114 @@ impl<'d, W: Write> Editor<'d, W> {
             .step_by(n_cols)
             .map(|c| Line::new(c, min(n_cols, n_bytes - c)))
             .collect::<Vec<Line>>();
+        let elf = Elf::parse(data_store.data());
 
         Editor {
             data_store,
             terminal: Terminal::new(writer),
-            height: height - PADDING_TOP - PADDING_BOTTOM,
+            width,
+            body_height: height - PADDING_TOP - PADDING_BOTTOM,
             n_cols,
             mode: EditorMode::Normal,
-            scroll: 0,
-            cursor_x: 0,
-            cursor_y: 0,
+            view: Viewport::new(0, height - PADDING_TOP - PADDING_BOTTOM),
+            split: None,
             cursor_offset: 0,
+            anchor: 0,
+            cmd_selection: None,
+            ascii_focus: false,
+            escape: None,
+            scrolloff: 0,
+            wrap: false,
+            compact_char: false,
+            dual: false,
+            dual_format: Format::UDec,
+            h_scroll: 0,
+            content_width: 0,
+            redraw: Redraw::All,
+            drawn: None,
+            pager: false,
+            back_stack: Vec::new(),
+            prev_offset: None,
+            count: None,
             cells,
             lines,
             cmd_buf: String::new(),
+            message: None,
+            status_format: DEFAULT_STATUS_FORMAT.to_string(),
+            overlay: None,
+            last_cmd: String::new(),
             finished: false,
-            dirty: false,
+            dirty: None,
+            journal: None,
+            original: HashMap::new(),
+            labels: HashMap::new(),
+            locks: vec![],
+            checksums: vec![],
+            show_modified: true,
+            separators: false,
+            fixed_width: false,
+            relative: false,
+            col_rules: false,
+            charset: Charset::Ascii,
+            ptr_width: Width::ADDRESS,
+            last_find: None,
+            collapse: false,
+            expanded: vec![],
             disasm_view: DisasmView::new(),
+            elf,
+            show_va: false,
+            pending: None,
+            recording: None,
+            macros: HashMap::new(),
+            replaying: false,
+            style: None,
+            prologues: vec![],
+            clipboard: vec![],
+            interrupt: Interrupt::default(),
         }
     }
 
     pub fn init(&mut self) {
         self.terminal.init();
-        self.set_cursor(0, 0);
+        self.redraw_all();
+        if !self.is_empty() {
+            self.set_cursor(0, 0);
+        }
         self.draw();
     }
 
     pub fn set_mode(&mut self, mode: EditorMode) {
+        if mode == EditorMode::Insert && self.pager {
+            self.message = Some("Cannot insert in pager mode".to_string());
+            return;
+        }
+        if mode == EditorMode::Insert && !self.check_writable() {
+            return;
+        }
+        if mode == EditorMode::Visual && self.mode != EditorMode::Visual {
+            self.anchor = self.cell_at_cursor().offset;
+        }
+        if mode == EditorMode::Command {
+            self.cmd_selection = self.selection();
+        }
+        self.escape = None;
         self.mode = mode;
     }
 
+    /// The byte range between the anchor and the cursor cell in visual mode, or the
+    /// selection a command was entered from.
+    pub fn selection(&self) -> Option<Range<usize>> {
+        if self.mode == EditorMode::Command {
+            return self.cmd_selection.clone();
+        }
+        if self.mode != EditorMode::Visual {
+            return None;
+        }
+        let anchor = self.cells.get(self.anchor).byte_range();
+        let cursor = self.cell_at_cursor().byte_range();
+        Some(min(anchor.start, cursor.start)..max(anchor.end, cursor.end))
+    }
+
     pub fn is_cmd(&self) -> bool {
         self.mode == EditorMode::Command
     }
@@ -139,16 +387,116 @@ impl<'d, W: Write> Editor<'d, W> {
         self.mode == EditorMode::Insert
     }
 
-    fn cell_index_at_col(&self, line_idx: usize, col: usize) -> usize {
-        let idx = min(
-            self.lines[line_idx].col_to_offset(col),
-            self.cells.len() - 1,
-        );
-        self.cells.get(idx).base_offset()
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn offset(&self) -> usize {
+        if self.is_empty() {
+            0
+        } else {
+            self.cell_at_cursor().offset
+        }
+    }
+
+    /// Records every byte written from now on in `journal`.
+    pub fn set_journal(&mut self, journal: File) {
+        self.journal = Some(journal);
+    }
+
+    pub fn set_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt = interrupt;
+    }
+
+    pub fn set_colors_enabled(&mut self, enabled: bool) {
+        self.terminal.set_colors_enabled(enabled);
+    }
+
+    pub fn set_status_format(&mut self, status_format: String) {
+        self.status_format = status_format;
+    }
+
+    pub fn set_prologues(&mut self, prologues: Vec<Vec<u8>>) {
+        self.prologues = prologues;
+    }
+
+    pub fn height(&self) -> usize {
+        self.view.height
+    }
+
+    /// Adapts the panes to a new terminal size, keeping the number of columns. Returns
+    /// whether the size changed and the screen needs to be redrawn.
+    pub fn resize(&mut self, width: usize, height: usize) -> bool {
+        let body_height = max(height.saturating_sub(PADDING_TOP + PADDING_BOTTOM), 3);
+        if width == self.width && body_height == self.body_height {
+            return false;
+        }
+        self.width = width;
+        self.body_height = body_height;
+        match &mut self.split {
+            None => self.view.height = body_height,
+            Some(other) => {
+                let (upper, lower) = if self.view.top == 0 {
+                    (&mut self.view, other)
+                } else {
+                    (other, &mut self.view)
+                };
+                upper.height = body_height / 2;
+                lower.top = upper.height + 1;
+                lower.height = body_height - upper.height - 1;
+            }
+        }
+        if let Some(other) = &mut self.split {
+            other.scroll_to_cursor(self.scrolloff, self.lines.len());
+        }
+        self.terminal.init();
+        self.redraw_all();
+        if !self.is_empty() {
+            self.set_cursor(self.view.cursor_x, self.view.cursor_y);
+        }
+        true
+    }
+
+    pub fn toggle_split(&mut self) {
+        if self.split.take().is_some() {
+            self.view.top = 0;
+            self.view.height = self.body_height;
+        } else {
+            let top_height = self.body_height / 2;
+            let mut other = self.view;
+            self.view.top = 0;
+            self.view.height = top_height;
+            other.top = top_height + 1; // leave a row for the separator
+            other.height = self.body_height - top_height - 1;
+            other.scroll_to_cursor(self.scrolloff, self.lines.len());
+            self.split = Some(other);
+        }
+        if !self.is_empty() {
+            self.set_cursor(self.view.cursor_x, self.view.cursor_y);
+        }
+    }
+
+    pub fn switch_pane(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+        if let Some(other) = &mut self.split {
+            mem::swap(&mut self.view, other);
+            // the layout may have changed while this pane was inactive
+            let y = min(self.view.cursor_y, self.lines.len() - 1);
+            self.set_cursor(self.view.cursor_x, y);
+        }
     }
 
-    fn cell_index_at_cursor(&self) -> usize {
-        self.cell_index_at_col(self.cursor_y, self.cursor_x)
+    fn cell_index_at_col(&self, line_idx: usize, col: usize) -> usize {
+        // an empty buffer has no cells to land on, and a stale line index may be past
+        // the layout after a resize or truncation
+        let line = match self.lines.get(line_idx) {
+            Some(line) if !self.cells.is_empty() => line,
+            _ => return 0,
+        };
+        let idx = min(line.col_to_offset(col), self.cells.len().saturating_sub(1));
+        self.cells.get(idx).base_offset()
     }
 
     fn cell_at_col(&self, line_idx: usize, col: usize) -> Cell {
@@ -160,22 +508,27 @@ impl<'d, W: Write> Editor<'d, W> {
     }
 
     fn cell_at_cursor(&self) -> Cell {
-        self.cell_at_col(self.cursor_y, self.cursor_x)
+        self.cell_at_col(self.view.cursor_y, self.view.cursor_x)
     }
 
     fn cell_at_cursor_mut(&mut self) -> &mut Cell {
-        self.cell_at_col_mut(self.cursor_y, self.cursor_x)
+        self.cell_at_col_mut(self.view.cursor_y, self.view.cursor_x)
     }
 
     pub fn move_cursor_next(&mut self) {
-        let line = &self.lines[self.cursor_y];
+        let line = &self.lines[self.view.cursor_y];
         let cell = self.cell_at_cursor();
 
         let mut new_cell_idx = cell.offset + cell.n_bytes();
-        let mut new_y = self.cursor_y;
+        let mut new_y = self.view.cursor_y;
+
+        if self.wrap && new_cell_idx >= self.cells.len() {
+            self.try_goto(0);
+            return;
+        }
 
         if new_cell_idx >= line.offset + line.len {
-            if self.cursor_y < self.lines.len() - 1 {
+            if self.view.cursor_y < self.lines.len() - 1 {
                 new_y += 1;
             } else {
                 new_cell_idx = self.cells.len() - 1;
@@ -187,18 +540,21 @@ impl<'d, W: Write> Editor<'d, W> {
     }
 
     pub fn move_cursor_prev(&mut self) {
-        let line = &self.lines[self.cursor_y];
+        let line = &self.lines[self.view.cursor_y];
         let cell = self.cell_at_cursor();
 
         if cell.offset < 1 {
+            if self.wrap {
+                self.set_cursor_end();
+            }
             return;
         }
 
         let mut new_cell_idx = self.cells.get(cell.offset - 1).base_offset();
-        let mut new_y = self.cursor_y;
+        let mut new_y = self.view.cursor_y;
 
         if new_cell_idx < line.offset {
-            if self.cursor_y > 0 {
+            if self.view.cursor_y > 0 {
                 new_y -= 1;
             } else {
                 new_cell_idx = 0;
@@ -210,15 +566,132 @@ impl<'d, W: Write> Editor<'d, W> {
     }
 
     pub fn move_cursor_y(&mut self, dy: isize) {
-        let mut new_y = self.cursor_y as isize + dy;
+        if self.pager {
+            self.scroll_page(dy);
+            return;
+        }
+        let mut new_y = self.view.cursor_y;
+        for _ in 0..dy.unsigned_abs() {
+            match self.next_visible_line(new_y, dy > 0) {
+                Some(y) => new_y = y,
+                None => break,
+            }
+        }
+
+        // keep the byte position within the line rather than the column, which maps to
+        // different bytes in lines with a different number of columns per byte
+        let line = &self.lines[self.view.cursor_y];
+        let offset = self.cell_at_cursor().offset;
+        let new_x = self.lines[new_y].col_of_same_byte(line, offset, &self.cells);
+        self.set_cursor(new_x, new_y);
+    }
+
+    /// Scrolls the view by `dy` lines in pager mode. The hidden cursor stays on the first
+    /// visible line, so that commands apply to what is on screen.
+    fn scroll_page(&mut self, dy: isize) {
+        if self.is_empty() {
+            return;
+        }
+        let max_scroll = self.lines.len().saturating_sub(self.view.height);
+        let scroll = self.view.scroll.saturating_add_signed(dy);
+        self.view.scroll = min(scroll, max_scroll);
+        self.view.cursor_y = self.view.scroll;
+        self.view.cursor_x = 0;
+        self.cursor_offset = 0;
+    }
+
+    /// Scrolls the lines horizontally by `steps` byte columns, as far as the widest line
+    /// of the last draw reaches past the window.
+    pub fn scroll_h(&mut self, steps: isize) {
+        let scroll = self
+            .h_scroll
+            .saturating_add_signed(steps * H_SCROLL_STEP as isize);
+        self.h_scroll = min(scroll, self.max_h_scroll());
+    }
+
+    fn max_h_scroll(&self) -> usize {
+        // the last column holds the scrollbar
+        let visible = self.width.saturating_sub(PADDING_LEFT + 1);
+        self.content_width.saturating_sub(visible)
+    }
+
+    pub fn set_pager(&mut self, pager: bool) {
+        self.pager = pager;
+        if pager && self.is_ins() {
+            self.set_mode(EditorMode::Normal);
+        }
+    }
+
+    fn next_visible_line(&self, y: usize, forward: bool) -> Option<usize> {
+        if forward {
+            (y + 1..self.lines.len()).find(|&i| !self.is_hidden(i))
+        } else {
+            (0..y).rev().find(|&i| !self.is_hidden(i))
+        }
+    }
+
+    /// Returns whether line `line_idx` holds the same bytes as the line before it.
+    fn repeats_previous(&self, line_idx: usize) -> bool {
+        if line_idx == 0 {
+            return false;
+        }
+        let (prev, line) = (&self.lines[line_idx - 1], &self.lines[line_idx]);
+        let data = self.data_store.data();
+        prev.len == line.len
+            && prev.cpb == line.cpb
+            && data[prev.cell_range()] == data[line.cell_range()]
+    }
+
+    /// Returns whether line `line_idx` is folded into a `*` row in the collapsed view.
+    fn is_hidden(&self, line_idx: usize) -> bool {
+        let offset = self.lines[line_idx].offset;
+        self.collapse
+            && self.repeats_previous(line_idx)
+            && !self.expanded.iter().any(|range| range.contains(&offset))
+    }
 
-        if new_y < 0 {
-            new_y = 0;
-        } else if new_y >= self.lines.len() as isize {
-            new_y = (self.lines.len() - 1) as isize;
+    /// Maps the rows of `view` to the lines drawn in them, with `None` for a `*` row
+    /// standing in for a run of hidden lines. The cursor line is never hidden.
+    fn visible_rows(&self, view: &Viewport) -> Vec<Option<usize>> {
+        let hidden = |i| i != view.cursor_y && self.is_hidden(i);
+        let mut rows = vec![];
+        let mut i = view.scroll;
+        while rows.len() < view.height && i < self.lines.len() {
+            if i != view.scroll && hidden(i) {
+                rows.push(None);
+                while i < self.lines.len() && hidden(i) {
+                    i += 1;
+                }
+            } else {
+                rows.push(Some(i));
+                i += 1;
+            }
         }
+        rows
+    }
+
+    pub fn toggle_collapse(&mut self) {
+        self.collapse = !self.collapse;
+        self.expanded.clear();
+    }
 
-        self.set_cursor(self.cursor_x, new_y as usize);
+    /// Shows the hidden lines of the collapsed run after (or around) the cursor line.
+    pub fn expand_run(&mut self) {
+        let y = self.view.cursor_y;
+        let mut start = if self.is_hidden(y) { y } else { y + 1 };
+        if start >= self.lines.len() || !self.is_hidden(start) {
+            self.message = Some("No collapsed lines at cursor".to_string());
+            return;
+        }
+        while self.is_hidden(start - 1) {
+            start -= 1;
+        }
+        let mut end = start;
+        while end < self.lines.len() && self.is_hidden(end) {
+            end += 1;
+        }
+        let range = self.lines[start].offset..self.lines[end - 1].cell_range().end;
+        self.expanded.push(range);
     }
 
     pub fn set_cursor_offset(&mut self, offset: usize) -> Result<(), usize> {
@@ -230,6 +703,51 @@ impl<'d, W: Write> Editor<'d, W> {
         Ok(())
     }
 
+    /// Moves the cursor to `offset`, or reports in the status bar that it is out of range
+    /// and leaves the cursor where it is.
+    pub fn try_goto(&mut self, offset: usize) -> bool {
+        match self.set_cursor_offset(offset) {
+            Ok(()) => true,
+            Err(_) => {
+                self.message = Some(format!("Offset {:#x} is out of range", offset));
+                false
+            }
+        }
+    }
+
+    /// Moves the cursor to `offset`, remembering the offset it jumped from.
+    fn jump(&mut self, offset: usize) {
+        let prev = self.offset();
+        if self.try_goto(offset) {
+            self.prev_offset = Some(prev);
+        }
+    }
+
+    /// Jumps back to the offset of the last jump, so that repeating it alternates
+    /// between two offsets.
+    pub fn jump_prev(&mut self) {
+        match self.prev_offset {
+            Some(offset) => self.jump(offset),
+            None => self.message = Some("No previous jump".to_string()),
+        }
+    }
+
+    pub fn goto_start(&mut self) {
+        self.jump(0);
+    }
+
+    /// Moves the cursor to the first byte and digit of the cursor cell.
+    pub fn goto_field_start(&mut self) {
+        let offset = self.cell_at_cursor().base_offset();
+        self.try_goto(offset);
+    }
+
+    pub fn goto_end(&mut self) {
+        let prev = self.offset();
+        self.set_cursor_end();
+        self.prev_offset = Some(prev);
+    }
+
     pub fn set_cursor_end(&mut self) {
         let y = self.lines.len() - 1;
         let x = self
@@ -242,24 +760,241 @@ impl<'d, W: Write> Editor<'d, W> {
 
     pub fn set_cursor(&mut self, x: usize, y: usize) {
         self.cursor_offset = 0;
-        self.cursor_x = x;
-        self.cursor_y = y;
+        self.view.cursor_x = x;
+        self.view.cursor_y = y;
 
         if x >= self.lines[y].len * self.lines[y].cpb {
-            self.cursor_x = self.lines[y].len * self.lines[y].cpb - 1;
+            self.view.cursor_x = self.lines[y].len * self.lines[y].cpb - 1;
+        }
+
+        self.scroll_to_cursor();
+    }
+
+    /// Scrolls the active view so that the cursor line is visible, counting each run
+    /// of hidden lines as a single row.
+    fn scroll_to_cursor(&mut self) {
+        let view = &self.view;
+        if !self.collapse || view.cursor_y < view.scroll {
+            self.view.scroll_to_cursor(self.scrolloff, self.lines.len());
+            return;
+        }
+        if self.visible_rows(view).contains(&Some(view.cursor_y)) {
+            return;
+        }
+        let mut scroll = view.cursor_y;
+        let mut n_rows = 1;
+        while let Some(prev) = self.next_visible_line(scroll, false) {
+            n_rows += if prev + 1 < scroll { 2 } else { 1 };
+            if n_rows > view.height {
+                break;
+            }
+            scroll = prev;
+        }
+        self.view.scroll = scroll;
+    }
+
+    /// Maps 1-based terminal coordinates to a `(col, line_idx)` cursor position in `view`.
+    fn screen_to_cursor(&self, view: &Viewport, x: u16, y: u16) -> Option<(usize, usize)> {
+        let row = (y as usize).checked_sub(1 + PADDING_TOP + view.top)?;
+        let px = (x as usize).checked_sub(1 + PADDING_LEFT)? + self.h_scroll;
+        if row >= view.height {
+            return None;
+        }
+        let line_idx = (*self.visible_rows(view).get(row)?)?;
+        let line = &self.lines[line_idx];
+        let col = px / 3; // each column is drawn as a space and two chars
+        if col >= line.len * line.cpb {
+            return None;
+        }
+        Some((col, line_idx))
+    }
+
+    pub fn click(&mut self, x: u16, y: u16) {
+        if self.is_empty() {
+            return;
+        }
+        if let Some((col, line_idx)) = self.screen_to_cursor(&self.view, x, y) {
+            self.set_cursor(col, line_idx);
+        } else if let Some(other) = self.split {
+            if let Some((col, line_idx)) = self.screen_to_cursor(&other, x, y) {
+                self.switch_pane();
+                self.set_cursor(col, line_idx);
+            }
+        }
+        // a new click drops any previous selection and anchors the next drag
+        if self.mode == EditorMode::Visual {
+            self.mode = EditorMode::Normal;
+        }
+        self.anchor = self.cell_at_cursor().offset;
+    }
+
+    pub fn drag(&mut self, x: u16, y: u16) {
+        if self.is_empty() {
+            return;
+        }
+        self.mode = EditorMode::Visual;
+
+        let row = y as isize - (1 + PADDING_TOP + self.view.top) as isize;
+        if row < 0 {
+            self.move_cursor_y(-1);
+        } else if row >= self.view.height as isize {
+            self.move_cursor_y(1);
+        } else if let Some((col, line_idx)) = self.screen_to_cursor(&self.view, x, y) {
+            self.set_cursor(col, line_idx);
         }
+    }
+
+    /// Moves the cursor to the next or previous offset that is a multiple of `alignment`,
+    /// skipping boundaries that lie within the current cell.
+    pub fn align_cursor(&mut self, alignment: usize, forward: bool) {
+        let cell = self.cell_at_cursor();
+        let target = if forward {
+            let end = cell.offset + cell.n_bytes();
+            end.div_ceil(alignment) * alignment
+        } else if cell.offset > 0 {
+            (cell.offset - 1) / alignment * alignment
+        } else {
+            return;
+        };
 
-        if y < self.scroll {
-            self.scroll = y;
-        } else if y >= self.scroll + self.height {
-            self.scroll = y - self.height + 1;
+        if target < self.cells.len() {
+            self.try_goto(target);
         }
     }
 
+    pub fn align_cursor_to_width(&mut self, forward: bool) {
+        let alignment = self.cell_at_cursor().n_bytes();
+        self.align_cursor(alignment, forward);
+    }
+
     pub fn scroll(&mut self, dy: isize) {
-        if self.scroll > 0 && self.scroll < self.lines.len() - 1 {
-            self.scroll = ((self.scroll as isize) + dy) as usize;
+        if self.is_empty() {
+            return;
+        }
+        if self.view.scroll > 0 && self.view.scroll < self.lines.len() - 1 {
+            self.view.scroll = ((self.view.scroll as isize) + dy) as usize;
+        }
+    }
+
+    /// Jumps to the start of line `n`, counted like the line number in the status bar,
+    /// and centers it.
+    pub fn goto_line(&mut self, n: usize) {
+        if self.is_empty() {
+            return;
+        }
+        let prev = self.offset();
+        self.set_cursor(0, min(n, self.lines.len() - 1));
+        self.scroll_cursor_to('z');
+        self.prev_offset = Some(prev);
+    }
+
+    /// Scrolls so that the cursor line is at the top (`t`), center (`z`) or bottom (`b`)
+    /// of the view, as far as the buffer allows.
+    pub fn scroll_cursor_to(&mut self, position: char) {
+        let view = &self.view;
+        let scroll = match position {
+            't' => view.cursor_y,
+            'z' => view.cursor_y.saturating_sub(view.height / 2),
+            'b' => view.cursor_y.saturating_sub(view.height - 1),
+            _ => return,
+        };
+        self.view.scroll = min(scroll, self.lines.len() - 1);
+    }
+
+    pub fn grow(&mut self, n_bytes: usize) {
+        let offset = self.offset();
+        if let Err(err) = self.data_store.grow(n_bytes) {
+            self.message = Some(format!("Cannot grow: {}", err));
+            return;
+        }
+
+        let len = self.data_store.data().len();
+        if self.data_store.backing_file().is_some() {
+            // the new bytes are only in the buffer until they are written
+            self.mark_dirty(len - n_bytes..len);
+        }
+        self.cells.resize(len);
+        self.rebuild_lines();
+        self.try_goto(offset);
+        self.message = Some(format!("Grew buffer to {:#x} bytes", len));
+    }
+
+    pub fn push_count_digit(&mut self, digit: usize) {
+        let count = self.count.unwrap_or(0);
+        self.count = Some(count.saturating_mul(10).saturating_add(digit));
+    }
+
+    /// Returns the pending count prefix, or 1 if there is none.
+    pub fn take_count(&mut self) -> usize {
+        max(self.count.take().unwrap_or(1), 1)
+    }
+
+    /// Makes the next key complete `action` instead of being handled on its own,
+    /// e.g. the register letter after `q` or `@`.
+    pub fn set_pending(&mut self, action: Action, count: usize) {
+        self.pending = Some((action, count));
+    }
+
+    pub fn take_pending(&mut self) -> Option<(Action, usize)> {
+        self.pending.take()
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    pub fn start_recording(&mut self, register: char) {
+        self.recording = Some((register, vec![]));
+    }
+
+    /// Stores the recorded keys, except for the key that stopped the recording.
+    pub fn stop_recording(&mut self) {
+        if let Some((register, mut keys)) = self.recording.take() {
+            keys.pop();
+            self.macros.insert(register, keys);
+        }
+    }
+
+    pub fn record_key(&mut self, key: Key) {
+        if let (Some((_, keys)), false) = (&mut self.recording, self.replaying) {
+            keys.push(key);
+        }
+    }
+
+    /// Returns the keys of the macro in `register` and marks the start of its playback.
+    /// Macros cannot be played back from within a macro.
+    pub fn start_replay(&mut self, register: char) -> Option<Vec<Key>> {
+        if self.replaying {
+            return None;
+        }
+        let keys = self.macros.get(&register).cloned();
+        if keys.is_none() {
+            self.message = Some(format!("Register {} is empty", register));
+        }
+        self.replaying = keys.is_some();
+        keys
+    }
+
+    pub fn end_replay(&mut self) {
+        self.replaying = false;
+    }
+
+    /// Applies `f` to `count` consecutive cells starting at the cursor, then returns the
+    /// cursor to the first cell.
+    pub fn repeat_on_cells(&mut self, count: usize, f: impl Fn(&mut Self)) {
+        let start = self.offset();
+        for i in 0..count {
+            f(self);
+            if i + 1 < count {
+                let offset = self.offset();
+                self.move_cursor_next();
+                if self.offset() == offset {
+                    break; // end of buffer
+                }
+            }
         }
+        let start = self.cells.get(start).base_offset();
+        self.try_goto(start);
     }
 
     pub fn switch_format(&mut self, rev: bool) {
@@ -286,47 +1021,160 @@ impl<'d, W: Write> Editor<'d, W> {
 
     pub fn set_format(&mut self, format: Format) {
         let cell = self.cell_at_cursor();
+        if format == Format::Guid && cell.width != Width::QWord128 {
+            self.message = Some("GUIDs need a 16 byte cell".to_string());
+            return;
+        }
         if cell.format == format || cell.n_bytes() * format.cols_per_byte() > self.n_cols {
             return;
         }
         self.cell_at_cursor_mut().format = format;
 
-        let min_cell = self.max_cpb_cell(self.cursor_y);
+        let min_cell = self.max_cpb_cell(self.view.cursor_y);
         let min_cpb = min_cell.format.cols_per_byte();
 
-        if min_cpb < self.lines[self.cursor_y].cpb {
-            self.lines[self.cursor_y].min_cpb = min_cpb;
-            self.merge_lines(self.cursor_y);
-        } else if min_cpb > self.lines[self.cursor_y].cpb {
-            self.split_line(self.cursor_y, min_cell.offset, min_cpb);
+        if min_cpb < self.lines[self.view.cursor_y].cpb {
+            self.lines[self.view.cursor_y].min_cpb = min_cpb;
+            self.merge_lines(self.view.cursor_y);
+        } else if min_cpb > self.lines[self.view.cursor_y].cpb {
+            self.split_line(self.view.cursor_y, min_cell.offset, min_cpb);
         } else {
-            self.lines[self.cursor_y].min_cpb = min_cpb;
+            self.lines[self.view.cursor_y].min_cpb = min_cpb;
         }
-        self.set_cursor_offset(cell.offset).unwrap();
+        self.try_goto(cell.offset);
     }
 
-    fn max_cpb_cell(&self, line_idx: usize) -> Cell {
-        let line_range = self.lines[line_idx].cell_range();
-        line_range
-            .map(|i| self.cells.get(i))
-            .filter(|c| c.offset == c.base_offset())
-            .max_by_key(|c| c.format.cols_per_byte())
-            .unwrap()
+    /// Lays out the lines from scratch, splitting each one until its cells fit.
+    fn rebuild_lines(&mut self) {
+        let n_bytes = self.cells.len();
+        let n_cols = self.n_cols;
+        self.lines = (0..n_bytes)
+            .step_by(n_cols)
+            .map(|c| Line::new(c, min(n_cols, n_bytes - c)))
+            .collect();
+
+        let mut i = 0;
+        while i < self.lines.len() {
+            let cpb = self.lines[i].cpb;
+            let min_cell = self.max_cpb_cell(i);
+            self.split_line(i, min_cell.offset, min_cell.format.cols_per_byte());
+            // a split line is visited again, as its first half may need to be split further
+            if self.lines[i].cpb == cpb {
+                i += 1;
+            }
+        }
+        self.check_lines();
+        self.redraw_all();
     }
 
-    fn split_line(&mut self, line_idx: usize, offset: usize, min_cpb: usize) {
-        if min_cpb > self.lines[line_idx].cpb {
-            let line = &mut self.lines[line_idx];
-            if line.len * min_cpb <= self.n_cols {
-                assert_eq!(line_idx, self.lines.len() - 1); // may only occur in last line
-                return;
-            }
+    /// Asserts that the lines tile the buffer without gaps or overlaps and that each
+    /// one fits into the columns.
+    fn check_lines(&self) {
+        let mut end = 0;
+        for line in &self.lines {
+            assert_eq!(line.offset, end);
+            assert!(line.len > 0 && line.len * line.cpb <= self.n_cols);
+            end += line.len;
+        }
+        assert_eq!(end, self.cells.len());
+    }
 
-            line.cpb *= 2;
-            let len = self.n_cols / line.cpb;
-            let mut new_line = Line {
-                offset: line.offset + len,
-                len: line.len - len,
+    /// Sets the format, width and byte order of all cells that have not been annotated.
+    pub fn set_default_cell(&mut self, cell: Cell) {
+        if cell.n_cols() > self.n_cols {
+            self.message = Some("Default cell does not fit in a line".to_string());
+            return;
+        }
+        self.cells.set_default(cell);
+        self.relayout();
+    }
+
+    /// Sets a second default cell that `toggle_alt` switches to, and switches to it.
+    pub fn set_alt_cell(&mut self, cell: Cell) {
+        if cell.n_cols() > self.n_cols {
+            self.message = Some("Alternate cell does not fit in a line".to_string());
+            return;
+        }
+        self.cells.set_alt(cell);
+        self.relayout();
+    }
+
+    /// Switches the cells that have not been annotated between the default cell and the
+    /// alternate one set with `:alt`.
+    pub fn toggle_alt(&mut self) {
+        if !self.cells.toggle_alt() {
+            self.message = Some("No alternate format, set one with :alt".to_string());
+            return;
+        }
+        self.relayout();
+    }
+
+    /// Shows the values of each line in `format` beside it, or toggles that display
+    /// with the last format if `format` is `None`.
+    pub fn set_dual(&mut self, format: Option<Format>) {
+        match format {
+            Some(format) => {
+                self.dual = true;
+                self.dual_format = format;
+            }
+            None => self.dual = !self.dual,
+        }
+    }
+
+    /// Copies the annotations in the `n_bytes` bytes from the cursor to the following
+    /// records of the same size, so that `count` records are laid out alike.
+    pub fn replicate_record(&mut self, n_bytes: usize, count: usize) {
+        let start = self.cell_at_cursor().offset;
+        let template = start..min(start.saturating_add(n_bytes), self.cells.len());
+        let misaligned = self
+            .cells
+            .custom_cells()
+            .iter()
+            .filter(|cell| template.contains(&cell.offset))
+            .any(|cell| !n_bytes.is_multiple_of(cell.n_bytes()));
+        if misaligned {
+            self.message = Some("Stride is not a multiple of the cell widths".to_string());
+            return;
+        }
+        let n_records = self.cells.replicate(template, n_bytes, count);
+        self.relayout();
+        self.message = Some(format!(
+            "Copied annotations to {} records of {:#x} bytes",
+            n_records, n_bytes
+        ));
+    }
+
+    /// Rebuilds the lines after the default cell changed, keeping the cursor offset.
+    fn relayout(&mut self) {
+        let offset = self.offset();
+        self.rebuild_lines();
+        if !self.is_empty() {
+            self.try_goto(offset);
+        }
+    }
+
+    fn max_cpb_cell(&self, line_idx: usize) -> Cell {
+        let line_range = self.lines[line_idx].cell_range();
+        line_range
+            .map(|i| self.cells.get(i))
+            .filter(|c| c.offset == c.base_offset())
+            .max_by_key(|c| c.format.cols_per_byte())
+            .unwrap()
+    }
+
+    fn split_line(&mut self, line_idx: usize, offset: usize, min_cpb: usize) {
+        if min_cpb > self.lines[line_idx].cpb {
+            let line = &mut self.lines[line_idx];
+            if line.len * min_cpb <= self.n_cols {
+                assert_eq!(line_idx, self.lines.len() - 1); // may only occur in last line
+                return;
+            }
+
+            line.cpb *= 2;
+            let len = self.n_cols / line.cpb;
+            let mut new_line = Line {
+                offset: line.offset + len,
+                len: line.len - len,
                 ..*line
             };
             line.len = len; // new_line.len < len for last (underfull) line
@@ -398,6 +1246,105 @@ impl<'d, W: Write> Editor<'d, W> {
         }
     }
 
+    /// Lays out the header fields of a recognized file format.
+    pub fn apply_magic(&mut self) {
+        let (name, fields) = if let Some(detected) = magic::detect(self.data_store.data()) {
+            detected
+        } else {
+            return;
+        };
+        for field in fields {
+            if field.byte_range().end > self.cells.len() {
+                continue;
+            }
+            self.try_goto(field.offset);
+            self.set_width(field.width);
+            self.set_format(field.format);
+            if self.cell_at_cursor().byte_order != field.byte_order {
+                self.switch_byte_order();
+            }
+        }
+        self.set_cursor(0, 0);
+        self.message = Some(format!("Detected {} header", name));
+    }
+
+    /// Copies the bytes of the selection, or of the cursor cell, to the clipboard.
+    pub fn yank(&mut self) {
+        let range = self
+            .selection()
+            .unwrap_or_else(|| self.cell_at_cursor().byte_range());
+        self.clipboard = self.data_store.data()[range.clone()].to_vec();
+        self.message = Some(format!(
+            "Yanked {} bytes at {:#x}",
+            range.len(),
+            range.start
+        ));
+        if self.mode == EditorMode::Visual {
+            self.mode = EditorMode::Normal;
+        }
+    }
+
+    /// Compares the clipboard with the bytes at the cursor and jumps to the first difference.
+    pub fn compare_clipboard(&mut self) {
+        if self.clipboard.is_empty() {
+            self.message = Some("Clipboard is empty".to_string());
+            return;
+        }
+        let start = self.cell_at_cursor().offset;
+        let data = &self.data_store.data()[start..];
+        let len = min(data.len(), self.clipboard.len());
+        let mismatch = (0..len).find(|&i| data[i] != self.clipboard[i]);
+        match mismatch {
+            Some(i) => {
+                self.jump(start + i);
+                self.message = Some(format!("First difference at {:#x}", start + i));
+            }
+            None if len < self.clipboard.len() => {
+                self.message = Some(format!(
+                    "First {} bytes are identical, buffer ends before the clipboard",
+                    len
+                ))
+            }
+            None => self.message = Some(format!("{} bytes are identical", len)),
+        }
+    }
+
+    /// Remembers the width, format and byte order of the cursor cell.
+    pub fn copy_style(&mut self) {
+        self.style = Some(self.cell_at_cursor());
+    }
+
+    /// Applies the copied style to the aligned cells in the selection, or in the cursor
+    /// line if there is no selection.
+    pub fn paste_style(&mut self) {
+        let style = if let Some(style) = self.style {
+            style
+        } else {
+            self.message = Some("No style copied".to_string());
+            return;
+        };
+        let range = self
+            .selection()
+            .unwrap_or_else(|| self.lines[self.view.cursor_y].cell_range());
+        let start = self.cell_at_cursor().offset;
+
+        let n_bytes = style.n_bytes();
+        let mut offset = range.start.div_ceil(n_bytes) * n_bytes;
+        while offset + n_bytes <= range.end {
+            self.try_goto(offset);
+            self.set_width(style.width);
+            self.set_format(style.format);
+            if self.cell_at_cursor().byte_order != style.byte_order {
+                self.switch_byte_order();
+            }
+            offset += n_bytes;
+        }
+
+        self.mode = EditorMode::Normal;
+        let start = self.cells.get(start).base_offset();
+        self.try_goto(start);
+    }
+
     pub fn switch_byte_order(&mut self) {
         let cell = self.cell_at_cursor_mut();
         cell.byte_order = cell.byte_order.toggle();
@@ -430,9 +1377,60 @@ impl<'d, W: Write> Editor<'d, W> {
         }
     }
 
+    /// Handles text pasted into the terminal as a whole, so that none of it is taken
+    /// as commands. Outside of insert and command mode it is ignored.
+    pub fn paste(&mut self, text: &str) {
+        if self.is_ins() {
+            text.chars().for_each(|c| self.insert(c));
+        } else if self.is_cmd() {
+            text.chars()
+                .filter(|c| !c.is_control())
+                .for_each(|c| self.type_cmd(c));
+        } else {
+            self.message = Some("Paste in insert or command mode".to_string());
+        }
+    }
+
+    /// Switches insert mode between editing the digits of cells and typing characters
+    /// that are written as bytes.
+    pub fn toggle_ascii_focus(&mut self) {
+        self.ascii_focus = !self.ascii_focus;
+        self.cursor_offset = 0;
+        self.escape = None;
+    }
+
+    /// Writes the byte typed as `c` in ASCII focus at the cursor, in file order within
+    /// the cursor cell, and advances.
+    fn insert_ascii(&mut self, c: char) {
+        let byte = match self.char_escape(c) {
+            Some(byte) => byte,
+            None => return,
+        };
+        let cell = self.cell_at_cursor();
+        let idx = min(self.cursor_offset, cell.n_bytes() - 1);
+        if self.write_bytes(cell.offset + idx, &[byte]) == 0 {
+            return;
+        }
+        if idx == cell.n_bytes() - 1 {
+            self.cursor_offset = 0;
+            self.move_cursor_next();
+        } else {
+            self.cursor_offset = idx + 1;
+        }
+    }
+
     pub fn insert(&mut self, c: char) {
+        if self.ascii_focus {
+            self.insert_ascii(c);
+            return;
+        }
         let cell = self.cell_at_cursor();
-        let digit = if let Some(d) = cell.format.parse_char(c) {
+        let digit = if cell.format == Format::Char {
+            self.char_escape(c)
+        } else {
+            cell.format.parse_char(c)
+        };
+        let digit = if let Some(d) = digit {
             d
         } else {
             return;
@@ -441,22 +1439,21 @@ impl<'d, W: Write> Editor<'d, W> {
             return;
         } // unimplemented
 
-        let data = self.data_store.data_mut();
         let cpb = cell.format.chars_per_byte();
         if self.cursor_offset < cpb * cell.n_bytes() {
-            let byte_idx = match cell.byte_order {
-                ByteOrder::BigEndian => self.cursor_offset / cpb,
-                ByteOrder::LittleEndian => cell.n_bytes() - self.cursor_offset / cpb - 1,
-            };
-            let old = data[cell.offset + byte_idx];
+            let offset = cell.offset + self.cursor_byte_index(&cell);
+            let old = self.data_store.data()[offset];
             let pos = (cpb - self.cursor_offset % cpb - 1) as u8;
-            data[cell.offset + byte_idx] = match cell.format {
+            let new = match cell.format {
                 Format::Hex => (old & !(0x0f << pos * 4)) | (digit << pos * 4),
                 Format::Oct => (old & !(0x07 << pos * 3)) | (digit << pos * 3),
                 Format::Bin => (old & !(0x01 << pos * 1)) | (digit << pos * 1),
                 Format::Char => digit,
                 _ => unimplemented!(),
             };
+            if self.write_bytes(offset, &[new]) == 0 {
+                return;
+            }
 
             if self.cursor_offset == cpb * cell.n_bytes() - 1 {
                 self.cursor_offset = 0;
@@ -465,210 +1462,2093 @@ impl<'d, W: Write> Editor<'d, W> {
                 self.cursor_offset += 1;
             }
         }
+    }
 
-        self.dirty = true;
+    /// The index in file order of the byte within `cell` that the next typed character
+    /// writes to.
+    fn cursor_byte_index(&self, cell: &Cell) -> usize {
+        if self.ascii_focus {
+            return min(self.cursor_offset, cell.n_bytes() - 1);
+        }
+        let idx = min(
+            self.cursor_offset / cell.format.chars_per_byte(),
+            cell.n_bytes() - 1,
+        );
+        match cell.byte_order {
+            ByteOrder::BigEndian => idx,
+            ByteOrder::LittleEndian => cell.n_bytes() - idx - 1,
+        }
     }
 
-    pub fn follow_pointer(&mut self) {
+    /// Lists the bytes of the cursor cell in the order they are displayed, as offsets
+    /// from the start of the cell, with a caret before the byte that insert mode writes.
+    fn ruler(&self) -> String {
         let cell = self.cell_at_cursor();
-        if cell.width != Width::ADDRESS {
-            return;
+        let current = self.cursor_byte_index(&cell);
+        let mut indices: Vec<_> = (0..cell.n_bytes()).collect();
+        if cell.byte_order == ByteOrder::LittleEndian {
+            indices.reverse();
         }
-        let data = self.data_store.data();
-        let offset = cell.parse_value(&data[cell.offset..]) as usize;
-        self.set_cursor_offset(offset).unwrap();
+        let marks: Vec<_> = indices
+            .into_iter()
+            .map(|i| format!("{}+{}", if i == current { "^" } else { "" }, i))
+            .collect();
+        marks.join(" ")
     }
 
-    pub fn type_cmd(&mut self, c: char) {
-        if c == '\n' {
-            let mut cmd = self.cmd_buf.splitn(2, ' ');
-            match cmd.next().unwrap() {
-                "w" => {
-                    self.data_store.write().unwrap();
-                    self.dirty = false
-                }
-                "q" => self.finished = true,
-                "d" => {
-                    let addr = self.cell_at_cursor().offset;
-                    let count = cmd.next().unwrap().parse::<usize>().unwrap();
-                    self.disasm_view
-                        .disassemble(addr, count, self.data_store.data());
-                }
-                cmd => {
-                    if let Ok(offset) = usize::from_str_radix(cmd, 16) {
-                        self.set_cursor_offset(offset).unwrap();
-                    } else {
-                        eprintln!("Unknown Command: \"{}\"", cmd)
-                    }
+    /// Interprets `c` as typed in char format, where `\n`, `\t`, `\r`, `\0`, `\\` and
+    /// `\xNN` stand for the byte they escape. Returns the byte to write once it is complete.
+    fn char_escape(&mut self, c: char) -> Option<u8> {
+        let mut escape = match self.escape.take() {
+            Some(escape) => escape,
+            None if c == '\\' => {
+                self.escape = Some(String::new());
+                return None;
+            }
+            None => return Format::Char.parse_char(c),
+        };
+        escape.push(c);
+        match escape.as_str() {
+            "n" => Some(b'\n'),
+            "t" => Some(b'\t'),
+            "r" => Some(b'\r'),
+            "0" => Some(0),
+            "\\" => Some(b'\\'),
+            "x" => {
+                self.escape = Some(escape);
+                None
+            }
+            _ if escape.starts_with('x') && c.is_ascii_hexdigit() => {
+                if escape.len() < 3 {
+                    self.escape = Some(escape);
+                    return None;
                 }
+                u8::from_str_radix(&escape[1..], 16).ok()
+            }
+            _ => {
+                self.message = Some(format!("Invalid escape: \"\\{}\"", escape));
+                None
             }
-            self.cmd_buf.clear();
-            self.mode = EditorMode::Normal;
-        } else if c == '\x08' {
-            self.cmd_buf.pop();
-        } else {
-            self.cmd_buf.push(c);
         }
     }
 
-    fn draw_status_bar(&self) {
-        self.terminal
-            .goto(1, 1 + (PADDING_TOP + self.height) as u16);
-        if self.mode == EditorMode::Command {
-            write!(self.terminal, ":{}", self.cmd_buf);
-        } else {
-            let cell = self.cell_at_cursor();
-            write!(
-                self.terminal,
-                "{:?} ({}, {}) {:#018x} {:?} {:?} {:?} {}%",
-                self.mode,
-                self.cursor_x,
-                self.cursor_y,
-                cell.offset,
-                cell.format,
-                cell.width,
-                cell.byte_order,
-                self.cursor_y * 100 / self.lines.len() as usize,
-            );
-        }
-        self.terminal.clear_line();
+    /// Returns whether any byte in `range` differs from its value when the buffer was loaded.
+    fn is_modified(&self, range: Range<usize>) -> bool {
+        let data = self.data_store.data();
+        range
+            .filter_map(|i| self.original.get(&i).map(|&b| (i, b)))
+            .any(|(i, b)| data[i] != b)
     }
 
-    fn escape_non_printable(chr: char) -> char {
-        match chr {
-            '\x0a' => '␊', // line feed
-            '\x0d' => '␍', // carriage return
-            '\x00' => '␀', // null
-            '\x07' => '␇', // bell
-            '\x08' => '␈', // backspace
-            '\x1b' => '␛', // escape
-            '\t' => '↹',   // tab
-            _ => '•',      // space
-        }
+    pub fn toggle_modified(&mut self) {
+        self.show_modified = !self.show_modified;
     }
 
-    fn draw_cell(&self, cell: &Cell, selected: bool, min_cols: usize) {
-        let data = &self.data_store.data()[cell.offset..];
-        assert!(data.len() >= cell.n_bytes());
-        write!(self.terminal, " ");
+    pub fn toggle_separators(&mut self) {
+        self.separators = !self.separators;
+    }
 
-        if selected {
-            self.terminal.bg_color(Color::Selected);
-        }
+    pub fn toggle_relative(&mut self) {
+        self.relative = !self.relative;
+    }
 
-        let cell_width = max(cell.n_cols(), min_cols) * 3 - 1;
-        let value = cell.format(cell.parse_value(data));
+    pub fn toggle_col_rules(&mut self) {
+        self.col_rules = !self.col_rules;
+    }
 
-        let fg_color = if value.is_null() {
-            Color::Null
-        } else if value.is_ascii() {
-            Color::Ascii
-        } else {
-            Color::Default
-        };
-        self.terminal.fg_color(fg_color);
+    pub fn toggle_fixed_width(&mut self) {
+        self.fixed_width = !self.fixed_width;
+    }
 
-        if cell.supports_cursor() && selected && self.is_ins() {
-            let (pre, cur, suf) = value.split(self.cursor_offset);
-            let w = cell.n_chars();
+    fn mark_dirty(&mut self, range: Range<usize>) {
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => min(dirty.start, range.start)..max(dirty.end, range.end),
+            None => range,
+        });
+    }
 
-            write!(self.terminal, "{:1$}", "", cell_width - w);
-            if let Some(pre) = pre {
-                write!(self.terminal, "{:1$}", pre, self.cursor_offset);
+    /// Saves the range of bytes changed since the last write.
+    pub fn write(&mut self) {
+        for checksum in self.checksums.clone() {
+            self.update_checksum(&checksum);
+        }
+        let range = if let Some(range) = self.dirty.clone() {
+            range
+        } else {
+            self.message = Some("No changes to write".to_string());
+            return;
+        };
+        match self.data_store.write(range.clone()) {
+            Ok(()) => {
+                self.dirty = None;
+                self.message = Some(format!("Wrote {} bytes at {:#x}", range.len(), range.start));
             }
+            Err(err) => self.message = Some(format!("Write failed: {}", err)),
+        }
+    }
 
-            self.terminal.fg_color(Color::Cursor);
-            write!(self.terminal, "{:1$}", cur, 1);
-            self.terminal.fg_color(fg_color);
+    /// Computes `checksum` and writes it in the byte order of the cell at its destination.
+    /// Returns the value, or `None` if it could not be written.
+    fn update_checksum(&mut self, checksum: &Checksum) -> Option<u32> {
+        let value = checksum.compute(self.data_store.data());
+        let dst = self.cells.get(checksum.dst);
+        let cell = Cell {
+            width: checksum.algorithm.width(),
+            byte_order: checksum.algorithm.byte_order().unwrap_or(dst.byte_order),
+            ..dst
+        };
+        let bytes = cell.encode_value(value.into());
+        if self.write_bytes(checksum.dst, &bytes) < bytes.len() {
+            return None;
+        }
+        Some(value)
+    }
 
-            if let Some(suf) = suf {
-                write!(self.terminal, "{:1$}", suf, w - self.cursor_offset - 1);
+    /// Writes the checksum `algorithm` of `src` at `dst`, and again before each write
+    /// if `auto` is set.
+    pub fn add_checksum(
+        &mut self,
+        algorithm: Algorithm,
+        src: Range<usize>,
+        dst: usize,
+        auto: bool,
+    ) {
+        if !self.check_writable() {
+            return;
+        }
+        let checksum = Checksum {
+            algorithm,
+            src,
+            dst,
+        };
+        let len = self.cells.len();
+        if checksum.src.start >= checksum.src.end || checksum.src.end > len {
+            self.message = Some("Invalid source range".to_string());
+            return;
+        }
+        if checksum
+            .dst
+            .checked_add(algorithm.width().n_bytes())
+            .is_none_or(|end| end > len)
+        {
+            self.message = Some(format!(
+                "{:?} checksum does not fit at {:#x}",
+                algorithm, dst
+            ));
+            return;
+        }
+        let value = match self.update_checksum(&checksum) {
+            Some(value) => value,
+            None => return, // the destination is locked
+        };
+        let mut message = format!(
+            "{:?} of {:#x}..{:#x} is {:#0w$x}",
+            algorithm,
+            checksum.src.start,
+            checksum.src.end,
+            value,
+            w = 2 + algorithm.width().n_bytes() * 2
+        );
+        if auto {
+            message.push_str(", updated on each write");
+            self.checksums.push(checksum);
+        }
+        self.message = Some(message);
+    }
+
+    /// Writes the changes like `write` and waits until they have reached the disk.
+    pub fn flush(&mut self) {
+        if self.dirty.is_some() {
+            self.write();
+            if self.dirty.is_some() {
+                return; // the write failed
             }
-        } else {
-            write!(self.terminal, "{:1$}", value, cell_width);
         }
+        self.message = Some(match self.data_store.sync() {
+            Ok(()) => "Flushed to disk".to_string(),
+            Err(err) => format!("Flush failed: {}", err),
+        });
+    }
 
-        self.terminal.reset_color();
+    /// Reports an error if the buffer cannot be modified.
+    fn check_writable(&mut self) -> bool {
+        if self.data_store.is_read_only() {
+            self.message = Some("Buffer is read-only".to_string());
+            return false;
+        }
+        true
     }
 
-    fn draw_header(&self, padding: usize) {
-        self.terminal.goto(1, 1);
-        write!(self.terminal, "{0:1$}", "", padding);
-        let cpb = self.lines[self.cursor_y].cpb;
-        for i in 0..(self.n_cols / cpb) {
-            if self.cursor_x / cpb == i {
-                write_color!(
-                    self.terminal,
-                    Color::Selected,
-                    " {1:2$}{:02x}",
-                    i,
-                    "",
-                    (cpb - 1) * 3
-                );
-            } else {
-                write!(self.terminal, " {1:2$}{:02x}", i, "", (cpb - 1) * 3);
+    /// Returns whether `range` has no locked bytes, and reports the lock otherwise.
+    fn check_unlocked(&mut self, range: Range<usize>) -> bool {
+        let lock = self
+            .locks
+            .iter()
+            .find(|lock| lock.start < range.end && range.start < lock.end);
+        match lock {
+            Some(lock) => {
+                self.message = Some(format!(
+                    "Bytes {:#x}..{:#x} are locked",
+                    lock.start, lock.end
+                ));
+                false
             }
+            None => true,
         }
-        self.terminal.clear_line();
     }
 
-    fn draw_offset(&self, line_idx: usize, offset: usize) {
-        if line_idx == self.cursor_y {
-            write_color!(self.terminal, Color::Selected, "{:#018x}", offset);
-        } else {
-            write!(self.terminal, "{:#018x}", offset);
+    fn is_locked(&self, range: Range<usize>) -> bool {
+        self.locks
+            .iter()
+            .any(|lock| lock.start < range.end && range.start < lock.end)
+    }
+
+    /// Locks `range` against writes.
+    pub fn lock(&mut self, range: Range<usize>) {
+        let range = range.start..min(range.end, self.cells.len());
+        if range.is_empty() {
+            self.message = Some("Nothing to lock".to_string());
+            return;
         }
+        self.message = Some(format!("Locked {:#x}..{:#x}", range.start, range.end));
+        self.locks.push(range);
     }
 
-    fn draw_line_ascii(&self, range: Range<usize>) {
-        let data = &self.data_store.data()[range];
-        write!(self.terminal, " {}", String::from_utf8_lossy(data));
+    pub fn unlock_all(&mut self) {
+        self.message = Some(format!("Removed {} locks", self.locks.len()));
+        self.locks.clear();
     }
 
-    pub fn draw(&mut self) {
-        self.draw_header(PADDING_LEFT);
+    /// Writes `bytes` starting at `offset`, truncated at the end of the buffer.
+    /// Returns the number of bytes written, which is 0 if any of them is locked.
+    fn write_bytes(&mut self, offset: usize, bytes: &[u8]) -> usize {
+        if !self.check_unlocked(offset..offset.saturating_add(bytes.len())) {
+            return 0;
+        }
+        let data = if let Some(data) = self.data_store.data_mut() {
+            data
+        } else {
+            return 0;
+        };
+        let n = min(bytes.len(), data.len().saturating_sub(offset));
+        if let Some(journal) = &mut self.journal {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let result = data[offset..offset + n]
+                .iter()
+                .zip(bytes)
+                .enumerate()
+                .try_for_each(|(i, (old, new))| {
+                    writeln!(
+                        journal,
+                        "{:#x} {:02x} {:02x} {}",
+                        offset + i,
+                        old,
+                        new,
+                        timestamp
+                    )
+                })
+                .and_then(|()| journal.flush());
+            if let Err(err) = result {
+                self.message = Some(format!("Journal write failed: {}", err));
+            }
+        }
+        for (i, &old) in data[offset..offset + n].iter().enumerate() {
+            self.original.entry(offset + i).or_insert(old);
+        }
+        data[offset..offset + n].copy_from_slice(&bytes[..n]);
+        if n > 0 {
+            self.mark_dirty(offset..offset + n);
+            self.redraw_range(offset..offset + n);
+        }
+        n
+    }
 
-        let mut offset = self.lines[self.scroll].offset;
+    /// Rewrites the bytes of the selection, or of the cursor cell if there is no
+    /// selection, with `transform`.
+    fn transform_bytes(&mut self, transform: fn(&mut [u8])) {
+        if !self.check_writable() {
+            return;
+        }
+        let range = self
+            .selection()
+            .unwrap_or_else(|| self.cell_at_cursor().byte_range());
+        let mut bytes = self.data_store.data()[range.clone()].to_vec();
+        transform(&mut bytes);
+        self.write_bytes(range.start, &bytes);
+    }
 
-        let mut i = self.scroll;
-        while i < min(self.lines.len(), self.scroll + self.height) {
-            assert!(self.lines[i].cell_range().end > offset);
+    /// Exchanges the `len` bytes at `a` with those at `b`. The ranges must not overlap.
+    pub fn swap_ranges(&mut self, a: usize, b: usize, len: usize) {
+        if !self.check_writable() {
+            return;
+        }
+        if max(a, b)
+            .checked_add(len)
+            .is_none_or(|end| end > self.cells.len())
+        {
+            self.message = Some("Range extends past the end of the buffer".to_string());
+            return;
+        }
+        if min(a, b) + len > max(a, b) {
+            self.message = Some("Ranges overlap".to_string());
+            return;
+        }
+        if !self.check_unlocked(a..a + len) || !self.check_unlocked(b..b + len) {
+            return;
+        }
+        let data = self.data_store.data();
+        let left = data[a..a + len].to_vec();
+        let right = data[b..b + len].to_vec();
+        self.write_bytes(a, &right);
+        self.write_bytes(b, &left);
+        self.message = Some(format!("Swapped {} bytes at {:#x} and {:#x}", len, a, b));
+    }
 
-            self.terminal
-                .goto(1, 1 + (PADDING_TOP + i - self.scroll) as u16);
-            self.draw_offset(i, offset);
+    /// Reverses the order of the bytes in the buffer, unlike `switch_byte_order`,
+    /// which only changes how they are displayed.
+    pub fn reverse_bytes(&mut self) {
+        self.transform_bytes(|bytes| bytes.reverse());
+    }
 
-            /*
-            let bi = match self.lines[i].buddy {
-                Buddy::Above => "^",
-                Buddy::Below => "v",
-                Buddy::None => "-",
-            };
-            write!(self.terminal, " {}{}{}{}",
-                                    self.lines[i].min_cpb,
-                                    self.lines[i].cpb,
-                                    self.lines[i].level,
-                                    bi);
-             */
+    pub fn swap_nibbles(&mut self) {
+        self.transform_bytes(|bytes| bytes.iter_mut().for_each(|b| *b = b.rotate_left(4)));
+    }
 
-            self.lines[i].offset = offset;
+    /// Adds `delta` to the value of the cursor cell, wrapping around at its width.
+    pub fn add_to_value(&mut self, delta: i128) {
+        if !self.check_writable() {
+            return;
+        }
+        let cell = self.cell_at_cursor();
+        let value = cell.parse_value(&self.data_store.data()[cell.offset..]);
+        let bytes = cell.encode_value(value.wrapping_add(delta as u128));
+        self.write_bytes(cell.offset, &bytes);
+    }
+
+    /// Writes `count` copies of the bytes of the selection, or of the cursor cell, right
+    /// after them.
+    pub fn repeat_bytes(&mut self, count: usize) {
+        if !self.check_writable() {
+            return;
+        }
+        let range = self
+            .selection()
+            .unwrap_or_else(|| self.cell_at_cursor().byte_range());
+        let pattern = self.data_store.data()[range.clone()].to_vec();
+        let len = pattern.len().saturating_mul(count);
+        let available = self.cells.len() - range.end;
+        let bytes: Vec<u8> = pattern
+            .iter()
+            .cycle()
+            .take(min(len, available))
+            .cloned()
+            .collect();
+        if !self.check_unlocked(range.end..range.end + bytes.len()) {
+            return;
+        }
+        let n = self.write_bytes(range.end, &bytes);
+        self.message = Some(if n < len {
+            format!("Wrote {} of {} bytes (end of buffer)", n, len)
+        } else {
+            format!("Wrote {} bytes", n)
+        });
+    }
+
+    /// Copies the contents of the file at `path` into the buffer at `offset`.
+    pub fn read_file(&mut self, path: &str, offset: usize) {
+        if !self.check_writable() {
+            return;
+        }
+        if offset >= self.cells.len() {
+            self.message = Some(format!("Offset {:#x} is out of range", offset));
+            return;
+        }
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                self.message = Some(format!("Cannot read {}: {}", path, err));
+                return;
+            }
+        };
+        if !self.check_unlocked(offset..offset + bytes.len()) {
+            return;
+        }
+        let n = self.write_bytes(offset, &bytes);
+        self.message = Some(if n < bytes.len() {
+            format!(
+                "Read {} of {} bytes at {:#x} (truncated at end of buffer)",
+                n,
+                bytes.len(),
+                offset
+            )
+        } else {
+            format!("Read {} bytes at {:#x}", n, offset)
+        });
+    }
+
+    /// Writes a table of all annotated cells and their current values to `path`.
+    pub fn write_report(&mut self, path: &str) {
+        let cells = self.cells.custom_cells();
+        let data = self.data_store.data();
+        let mut report = String::new();
+        for cell in &cells {
+            let value = cell.parse_value(&data[cell.byte_range()]);
+            let value = cell.format(value).with_charset(self.charset);
+            report += &format!(
+                "{:#018x}  {:<8}  {:<5}  {:<12}  {}\n",
+                cell.offset,
+                format!("{:?}", cell.width),
+                format!("{:?}", cell.format),
+                format!("{:?}", cell.byte_order),
+                value.to_string().trim()
+            );
+        }
+        self.message = Some(match fs::write(path, report) {
+            Ok(()) => format!("Wrote {} cells to {}", cells.len(), path),
+            Err(err) => format!("Cannot write {}: {}", path, err),
+        });
+    }
+
+    /// Writes the bytes in `range` to a new file at `path`.
+    pub fn carve(&mut self, range: Range<usize>, path: &str) {
+        if range.start >= range.end || range.end > self.cells.len() {
+            self.message = Some(format!(
+                "Invalid range {:#x}..{:#x}",
+                range.start, range.end
+            ));
+            return;
+        }
+        let mut file = match File::create(path) {
+            Ok(file) => file,
+            Err(err) => {
+                self.message = Some(format!("Cannot create {}: {}", path, err));
+                return;
+            }
+        };
+        let data = &self.data_store.data()[range.clone()];
+        let mut written = 0;
+        self.interrupt.begin();
+        let result = data
+            .chunks(scan::PROGRESS_INTERVAL)
+            .try_for_each(|chunk| {
+                if self.interrupt.is_cancelled() {
+                    return Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+                }
+                self.draw_progress("Writing", written, data.len());
+                file.write_all(chunk)?;
+                written += chunk.len();
+                Ok(())
+            })
+            .and_then(|()| file.flush());
+        self.interrupt.end();
+        self.message = Some(match result {
+            Ok(()) => format!(
+                "Wrote {} bytes from {:#x} to {}",
+                written, range.start, path
+            ),
+            Err(err) => format!(
+                "Carving to {} failed after {} bytes: {}",
+                path, written, err
+            ),
+        });
+    }
+
+    pub fn write_hex(&mut self, hex: &str) {
+        if !self.check_writable() {
+            return;
+        }
+        let bytes = if let Some(bytes) = parse_hex_bytes(hex) {
+            bytes
+        } else {
+            self.message = Some(format!("Invalid hex string: \"{}\"", hex));
+            return;
+        };
+
+        let offset = self.cell_at_cursor().offset;
+        if !self.check_unlocked(offset..offset + bytes.len()) {
+            return;
+        }
+        let n = self.write_bytes(offset, &bytes);
+        self.try_goto(min(offset + n, self.cells.len() - 1));
+        self.message = Some(if n < bytes.len() {
+            format!("Wrote {} of {} bytes (end of buffer)", n, bytes.len())
+        } else {
+            format!("Wrote {} bytes", n)
+        });
+    }
+
+    fn show_results(&mut self, title: String, entries: Vec<(usize, String)>) {
+        let overlay = Overlay::new(title, entries);
+        if overlay.is_empty() {
+            self.message = Some("No results".to_string());
+        } else {
+            self.overlay = Some(overlay);
+        }
+    }
+
+    pub fn has_overlay(&self) -> bool {
+        self.overlay.is_some()
+    }
+
+    pub fn close_overlay(&mut self) {
+        self.overlay = None;
+    }
+
+    pub fn overlay_move(&mut self, delta: isize) {
+        let height = PADDING_TOP + self.body_height;
+        if let Some(overlay) = &mut self.overlay {
+            overlay.move_selection(delta, height);
+        }
+    }
+
+    pub fn overlay_select(&mut self) {
+        if let Some(offset) = self.overlay.take().and_then(|o| o.selected_offset()) {
+            self.jump(offset);
+        }
+    }
+
+    /// Lists the entropy of as many equally sized blocks of the buffer as fit on the screen,
+    /// each with a bar scaled to the terminal width.
+    pub fn show_entropy(&mut self) {
+        let data = self.data_store.data();
+        let n_blocks = min(data.len(), max(self.body_height, 2) - 1);
+        let block_size = data.len().div_ceil(n_blocks);
+        let bar_width = self.width.saturating_sub(2 * Width::ADDRESS.n_bytes() + 11);
+        let mut entries = vec![];
+        self.interrupt.begin();
+        for (i, block) in data.chunks(block_size).enumerate() {
+            if self.interrupt.is_cancelled() {
+                break;
+            }
+            self.draw_progress("Computing entropy", i * block_size, data.len());
+            let entropy = scan::entropy(block);
+            let bar = bar(entropy / 8.0, bar_width);
+            entries.push((i * block_size, format!("{:.2} {}", entropy, bar)));
+        }
+        self.interrupt.end();
+        if self.interrupt.is_cancelled() {
+            self.message = Some("Entropy cancelled".to_string());
+            return;
+        }
+        let title = format!("Entropy of {:#x} byte blocks", block_size);
+        self.show_results(title, entries);
+    }
+
+    /// Lists the byte values in the selection, or in the whole buffer if there is none,
+    /// by how often they occur. Each entry leads to the first occurrence of its value.
+    pub fn show_frequencies(&mut self) {
+        let range = self.selection().unwrap_or(0..self.cells.len());
+        let data = &self.data_store.data()[range.clone()];
+        let mut counts = [0usize; 256];
+        let mut first = [None; 256];
+        for (i, &b) in data.iter().enumerate() {
+            counts[b as usize] += 1;
+            first[b as usize].get_or_insert(range.start + i);
+        }
+        let mut values: Vec<usize> = (0..256).filter(|&b| counts[b] > 0).collect();
+        values.sort_by_key(|&b| Reverse(counts[b]));
+
+        let max_count = counts[values[0]];
+        let bar_width = self.width.saturating_sub(2 * Width::ADDRESS.n_bytes() + 32);
+        let entries = values
+            .into_iter()
+            .map(|b| {
+                let c = char::from(b as u8);
+                let c = if c.is_ascii_graphic() { c } else { '.' };
+                let percent = counts[b] as f64 * 100.0 / data.len() as f64;
+                let bar = bar(counts[b] as f64 / max_count as f64, bar_width);
+                let text = format!("{:02x} {} {:>10} {:6.2}% {}", b, c, counts[b], percent, bar);
+                (first[b].unwrap(), text)
+            })
+            .collect();
+        let title = format!("Byte frequencies in {:#x}..{:#x}", range.start, range.end);
+        self.show_results(title, entries);
+    }
+
+    /// Compares the buffer with the current contents of its file on disk and lists the
+    /// ranges that differ.
+    pub fn diff_disk(&mut self) {
+        let file = match self.data_store.backing_file() {
+            Some(file) => file,
+            None => {
+                self.message = Some("Buffer is not backed by a writable file".to_string());
+                return;
+            }
+        };
+        let data = self.data_store.data();
+        let mut ranges: Vec<Range<usize>> = vec![];
+        let mut disk = vec![0; min(scan::PROGRESS_INTERVAL, data.len())];
+        let mut offset = 0;
+        self.interrupt.begin();
+        let result = loop {
+            if offset >= data.len() {
+                break Ok(());
+            }
+            if self.interrupt.is_cancelled() {
+                break Err(io::Error::new(io::ErrorKind::Interrupted, "cancelled"));
+            }
+            self.draw_progress("Comparing", offset, data.len());
+            let len = min(disk.len(), data.len() - offset);
+            let n = match file.read_at(&mut disk[..len], offset as u64) {
+                Ok(n) => n,
+                Err(err) => break Err(err),
+            };
+            // bytes past the end of a truncated file count as changed
+            let changed = (0..len).filter(|&i| i >= n || disk[i] != data[offset + i]);
+            for i in changed.map(|i| offset + i) {
+                match ranges.last_mut() {
+                    Some(range) if range.end == i => range.end += 1,
+                    _ => ranges.push(i..i + 1),
+                }
+            }
+            offset += len;
+        };
+        self.interrupt.end();
+        if let Err(err) = result {
+            self.message = Some(format!("Comparing with disk failed: {}", err));
+            return;
+        }
+        if ranges.is_empty() {
+            self.message = Some("Buffer is identical to the file on disk".to_string());
+            return;
+        }
+        let n_bytes: usize = ranges.iter().map(|range| range.len()).sum();
+        let title = format!("{} bytes differ from disk", n_bytes);
+        let entries = ranges
+            .into_iter()
+            .map(|range| (range.start, format!("{} bytes", range.len())))
+            .collect();
+        self.show_results(title, entries);
+    }
+
+    /// Compares the `len` bytes at `a` with those at `b`, listing the rows of 8 bytes
+    /// that differ. Each row shows both sides and marks the differing bytes with `x`.
+    pub fn diff_ranges(&mut self, a: usize, b: usize, len: usize) {
+        const ROW_LEN: usize = 8;
+        let data = self.data_store.data();
+        if max(a, b)
+            .checked_add(len)
+            .is_none_or(|end| end > data.len())
+        {
+            self.message = Some("Range extends past the end of the buffer".to_string());
+            return;
+        }
+        let (left, right) = (&data[a..a + len], &data[b..b + len]);
+        let hex = |bytes: &[u8]| {
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("{:1$}", hex.join(" "), ROW_LEN * 3 - 1)
+        };
+        let mut n_diffs = 0;
+        let mut entries = vec![];
+        for (i, (l, r)) in left.chunks(ROW_LEN).zip(right.chunks(ROW_LEN)).enumerate() {
+            let marks: String = l
+                .iter()
+                .zip(r)
+                .map(|(l, r)| if l == r { '.' } else { 'x' })
+                .collect();
+            let n = marks.matches('x').count();
+            if n > 0 {
+                n_diffs += n;
+                let text = format!("{}  {}  {}", hex(l), hex(r), marks);
+                entries.push((a + i * ROW_LEN, text));
+            }
+        }
+        if n_diffs == 0 {
+            self.message = Some(format!("{} bytes are identical", len));
+            return;
+        }
+        let title = format!("{:#x} vs {:#x}: {} bytes differ", a, b, n_diffs);
+        self.show_results(title, entries);
+    }
+
+    /// Shows the raw bytes of the cursor cell in file order, whatever its format.
+    pub fn show_bytes(&mut self) {
+        let cell = self.cell_at_cursor();
+        let bytes = &self.data_store.data()[cell.byte_range()];
+        let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        self.message = Some(format!(
+            "{:#x}: {} ({:?})",
+            cell.offset,
+            hex.join(" "),
+            cell.byte_order
+        ));
+    }
+
+    /// Lists `count` consecutive elements laid out like `cell`, starting at the cursor,
+    /// stopping early at the end of the buffer.
+    pub fn show_array(&mut self, cell: Cell, count: usize) {
+        let data = self.data_store.data();
+        let start = self.cell_at_cursor().offset;
+        let count = min(count, (data.len() - start) / cell.n_bytes());
+        let entries = (0..count)
+            .map(|i| {
+                let offset = start + i * cell.n_bytes();
+                let value = cell.parse_value(&data[offset..]);
+                let value = cell.format(value).with_charset(self.charset);
+                (offset, format!("[{}] {}", i, value.to_string().trim()))
+            })
+            .collect();
+        let title = format!("{} x {:?} {:?}", count, cell.width, cell.format);
+        self.show_results(title, entries);
+    }
+
+    /// Reads a length with the width and byte order of `cell` at the cursor and selects
+    /// that many bytes after it. If `includes_prefix` is set, the length counts the
+    /// prefix itself.
+    pub fn select_length_blob(&mut self, cell: Cell, includes_prefix: bool) {
+        let data = self.data_store.data();
+        let prefix = self.cell_at_cursor().offset;
+        let start = prefix + cell.n_bytes();
+        if start > data.len() {
+            self.message = Some("Length runs past the end of the buffer".to_string());
+            return;
+        }
+        let mut len = cell.parse_value(&data[prefix..]);
+        if includes_prefix {
+            len = len.saturating_sub(cell.n_bytes() as u128);
+        }
+        let len = min(len, (data.len() - start) as u128) as usize;
+        if len == 0 {
+            self.message = Some(format!("Empty blob at {:#x}", start));
+            return;
+        }
+        self.jump(start);
+        self.anchor = self.cell_at_cursor().offset;
+        self.mode = EditorMode::Visual;
+        self.try_goto(start + len - 1);
+        self.message = Some(format!(
+            "Selected {:#x}..{:#x} ({} bytes)",
+            start,
+            start + len,
+            len
+        ));
+    }
+
+    pub fn find_strings(&mut self, min_len: usize, utf16: bool) {
+        let data = self.data_store.data();
+        let (title, strings) = if utf16 {
+            ("UTF-16LE strings", scan::utf16_strings(data, min_len))
+        } else {
+            ("Strings", scan::ascii_strings(data, min_len))
+        };
+        self.show_results(format!("{} (min. length {})", title, min_len), strings);
+    }
+
+    /// Searches for an aligned value of `n_bytes` bytes, read with the byte order of
+    /// the cursor cell, and jumps to the first match after the cursor.
+    pub fn find_value(&mut self, n_bytes: usize, value: u128) {
+        let width = match Width::from_n_bytes(n_bytes) {
+            Some(width) => width,
+            None => {
+                self.message = Some("Width must be 1, 2, 4, 8 or 16 bytes".to_string());
+                return;
+            }
+        };
+        if n_bytes < 16 && value >> (8 * n_bytes) != 0 {
+            self.message = Some(format!("{:#x} does not fit in {} bytes", value, n_bytes));
+            return;
+        }
+        let cell = Cell::new(0, Format::Hex, width, self.cell_at_cursor().byte_order);
+        self.last_find = Some(Search::Value(cell, value));
+        self.find_next(true);
+    }
+
+    /// Repeats the last `:findval` or `:anomaly` search in the given direction.
+    pub fn find_next(&mut self, forward: bool) {
+        let search = if let Some(last_find) = self.last_find.clone() {
+            last_find
+        } else {
+            self.message = Some("No previous search".to_string());
+            return;
+        };
+        let start = self.cell_at_cursor().offset;
+        let data = self.data_store.data();
+        let total = if forward { data.len() - start } else { start };
+        let mut progress = |scanned| {
+            self.draw_progress("Searching", scanned, total);
+            !self.interrupt.is_cancelled()
+        };
+        self.interrupt.begin();
+        let result = match &search {
+            Search::Value(cell, value) => {
+                scan::find_value(data, *cell, *value, start, forward, &mut progress)
+            }
+            Search::Anomaly(pattern) => {
+                scan::find_anomaly(data, pattern, start, forward, &mut progress)
+            }
+        };
+        self.interrupt.end();
+        self.message = Some(match (result, search) {
+            (Ok(Some(offset)), Search::Value(_, value)) => {
+                self.jump(offset);
+                format!("Found {:#x} at {:#x}", value, offset)
+            }
+            (Ok(Some(offset)), Search::Anomaly(_)) => {
+                self.jump(offset);
+                format!("Byte at {:#x} breaks the pattern", offset)
+            }
+            (Ok(None), Search::Value(_, value)) => format!("Value {:#x} not found", value),
+            (Ok(None), Search::Anomaly(_)) => "No more bytes break the pattern".to_string(),
+            (Err(scan::Cancelled), _) => "Search cancelled".to_string(),
+        });
+    }
+
+    /// Lists the runs of bytes that differ from `pattern` repeated from the start of the
+    /// buffer, and makes `n` and `N` jump between them.
+    pub fn find_anomalies(&mut self, pattern: Vec<u8>) {
+        let data = self.data_store.data();
+        let mut runs: Vec<Range<usize>> = vec![];
+        if data[0] != pattern[0] {
+            runs.push(0..1);
+        }
+        let mut offset = 0;
+        self.interrupt.begin();
+        let result = loop {
+            match scan::find_anomaly(data, &pattern, offset, true, &mut |scanned| {
+                self.draw_progress("Searching", offset + scanned, data.len());
+                !self.interrupt.is_cancelled()
+            }) {
+                Ok(Some(i)) => {
+                    match runs.last_mut() {
+                        Some(run) if run.end == i => run.end += 1,
+                        _ => runs.push(i..i + 1),
+                    }
+                    offset = i;
+                }
+                Ok(None) => break Ok(()),
+                Err(cancelled) => break Err(cancelled),
+            }
+        };
+        self.interrupt.end();
+        if result.is_err() {
+            self.message = Some("Search cancelled".to_string());
+            return;
+        }
+        self.last_find = Some(Search::Anomaly(pattern));
+        if runs.is_empty() {
+            self.message = Some("All bytes match the pattern".to_string());
+            return;
+        }
+        let n_bytes: usize = runs.iter().map(|run| run.len()).sum();
+        let title = format!("{} bytes break the pattern", n_bytes);
+        let entries = runs
+            .into_iter()
+            .map(|run| (run.start, format!("{} bytes", run.len())))
+            .collect();
+        self.show_results(title, entries);
+    }
+
+    /// Moves the cursor to the start of the instruction `n` instructions away from the one
+    /// at the cursor. Instructions after the disassembled ones are disassembled on demand,
+    /// but x86 code cannot be decoded backwards from an arbitrary offset.
+    pub fn move_insn(&mut self, n: isize) {
+        let idx = match self.disasm_view.index_at(self.offset()) {
+            Some(idx) => idx,
+            None => {
+                self.message = Some("No disassembled instruction at the cursor".to_string());
+                return;
+            }
+        };
+        let target = match idx.checked_add_signed(n) {
+            Some(target) => target,
+            None => {
+                self.message = Some("No instruction before the disassembled ones".to_string());
+                return;
+            }
+        };
+        let target = if target < self.disasm_view.len() {
+            target
+        } else {
+            // an instruction is at most 15 bytes long
+            let (start, _) = self.disasm_view.get(idx).unwrap();
+            let len = (n.unsigned_abs() + 1).saturating_mul(15);
+            self.disasm_view
+                .disassemble_bytes(start.start, len, self.data_store.data());
+            n.unsigned_abs()
+        };
+        match self.disasm_view.get(target) {
+            Some((range, _)) => {
+                self.try_goto(range.start);
+            }
+            None => self.message = Some("No instruction that far".to_string()),
+        }
+    }
+
+    /// Opens the command line with the instruction at the cursor, to be edited and
+    /// assembled back with `:asm`.
+    #[cfg(feature = "keystone")]
+    pub fn edit_insn(&mut self) {
+        match self.disasm_view.insn_at(self.offset()) {
+            Some((_, text)) => {
+                self.set_mode(EditorMode::Command);
+                self.cmd_buf = format!("asm {}", text);
+            }
+            None => self.message = Some("No disassembled instruction at the cursor".to_string()),
+        }
+    }
+
+    /// Assembles `text` and writes it over the instruction at the cursor. A shorter
+    /// encoding is padded with NOPs, a longer one is rejected.
+    #[cfg(feature = "keystone")]
+    pub fn patch_insn(&mut self, text: &str) {
+        if !self.check_writable() {
+            return;
+        }
+        let range = match self.disasm_view.insn_at(self.offset()) {
+            Some((range, _)) => range,
+            None => {
+                self.message = Some("No disassembled instruction at the cursor".to_string());
+                return;
+            }
+        };
+        let address = self.disasm_view.base().wrapping_add(range.start as u64);
+        let encoding = match asm::assemble(text, address) {
+            Ok(encoding) => encoding,
+            Err(err) => {
+                self.message = Some(format!("Cannot assemble \"{}\": {}", text, err));
+                return;
+            }
+        };
+        let n_bytes = encoding.len();
+        let bytes = match asm::pad_to_fit(encoding, range.len()) {
+            Some(bytes) => bytes,
+            None => {
+                self.message = Some(format!(
+                    "Encoding of {} bytes does not fit in the {} bytes of the instruction",
+                    n_bytes,
+                    range.len()
+                ));
+                return;
+            }
+        };
+        if self.write_bytes(range.start, &bytes) == 0 {
+            return;
+        }
+        // the instructions after a patch decode differently if it changed their bytes
+        self.disasm_view
+            .set_base(self.disasm_view.base(), self.data_store.data());
+        self.message = Some(format!(
+            "Assembled {} bytes at {:#x} and {} NOPs",
+            n_bytes,
+            range.start,
+            range.len() - n_bytes
+        ));
+    }
+
+    /// Jumps to the next function prologue after the cursor and disassembles from there.
+    pub fn next_function(&mut self) {
+        let start = self.cell_at_cursor().offset;
+        let data = self.data_store.data();
+        let total = data.len() - start;
+        self.interrupt.begin();
+        let result = scan::find_patterns(data, &self.prologues, start, &mut |scanned| {
+            self.draw_progress("Searching", scanned, total);
+            !self.interrupt.is_cancelled()
+        });
+        self.interrupt.end();
+        match result {
+            Ok(Some(offset)) => {
+                let count = max(self.disasm_view.len(), self.body_height);
+                self.disasm_view
+                    .disassemble(offset, count, self.data_store.data());
+                self.jump(offset);
+            }
+            Ok(None) => self.message = Some("No function prologue found".to_string()),
+            Err(scan::Cancelled) => self.message = Some("Search cancelled".to_string()),
+        }
+    }
+
+    /// Reads a pointer of the configured pointer width at the cursor and jumps to it,
+    /// regardless of the width of the cursor cell.
+    pub fn follow_pointer(&mut self) {
+        let cell = Cell {
+            width: self.ptr_width,
+            ..self.cell_at_cursor()
+        };
+        if cell.byte_range().end > self.cells.len() {
+            self.message = Some("Pointer extends past the end of the buffer".to_string());
+            return;
+        }
+        self.goto_value_of(cell);
+    }
+
+    /// Interprets the value of the cursor cell as an offset and jumps there.
+    pub fn goto_value(&mut self) {
+        self.goto_value_of(self.cell_at_cursor());
+    }
+
+    fn goto_value_of(&mut self, cell: Cell) {
+        let data = self.data_store.data();
+        let addr = cell.parse_value(&data[cell.offset..]);
+        let offset = if let Some(offset) = self.addr_to_offset(addr) {
+            offset
+        } else {
+            self.message = Some(format!("Offset {:#x} is out of range", addr));
+            return;
+        };
+        self.back_stack.push(cell.offset);
+        self.jump(offset);
+    }
+
+    /// Translates an address entered or read by the user to a file offset. In virtual
+    /// address mode, addresses are looked up in the ELF segments.
+    fn addr_to_offset(&self, addr: u128) -> Option<usize> {
+        let offset = match &self.elf {
+            Some(elf) if self.show_va => elf.va_to_offset(addr.try_into().ok()?)?,
+            _ => addr.try_into().ok()?,
+        };
+        Some(offset).filter(|&offset| offset < self.cells.len())
+    }
+
+    pub fn toggle_va(&mut self) {
+        if self.elf.is_none() {
+            self.message = Some("Not an ELF file".to_string());
+            return;
+        }
+        self.show_va = !self.show_va;
+    }
+
+    pub fn set_ptr_width(&mut self, n_bytes: usize) {
+        match Width::from_n_bytes(n_bytes) {
+            Some(width) if n_bytes <= 8 => self.ptr_width = width,
+            _ => self.message = Some("Pointer width must be 1, 2, 4 or 8 bytes".to_string()),
+        }
+    }
+
+    /// Jumps to the target of the direct jump or call under the cursor and
+    /// disassembles from there.
+    pub fn follow_branch(&mut self) {
+        let offset = self.cell_at_cursor().offset;
+        let target = match self.disasm_view.branch_target(offset) {
+            Some(Some(target)) => target,
+            Some(None) => {
+                self.message = Some("Not a direct jump or call".to_string());
+                return;
+            }
+            None => {
+                self.message = Some("No disassembly at cursor".to_string());
+                return;
+            }
+        };
+        let target = match target.checked_sub(self.disasm_view.base()) {
+            Some(target) if target < self.cells.len() as u64 => target,
+            _ => {
+                self.message = Some(format!("Address {:#x} is out of range", target));
+                return;
+            }
+        };
+        let count = self.disasm_view.len();
+        self.disasm_view
+            .disassemble(target as usize, count, self.data_store.data());
+        self.back_stack.push(offset);
+        self.jump(target as usize);
+    }
+
+    /// Jumps to the first default cell after the run of annotated cells at the cursor.
+    pub fn goto_struct_end(&mut self) {
+        let start = self.cell_at_cursor().offset;
+        if !self.cells.is_custom(start) {
+            self.message = Some("Cursor is not on an annotated cell".to_string());
+            return;
+        }
+        let end = self.cells.end_of_custom_run(start);
+        if end == self.cells.len() {
+            self.message = Some("Annotations extend to the end of the buffer".to_string());
+            self.set_cursor_end();
+        } else {
+            self.jump(end);
+        }
+    }
+
+    /// Jumps from a bracket byte under the cursor to its matching counterpart.
+    pub fn goto_matching_bracket(&mut self) {
+        let offset = self.cell_at_cursor().offset;
+        let data = self.data_store.data();
+        if !scan::is_bracket(data[offset]) {
+            self.message = Some("Cursor is not on a bracket".to_string());
+            return;
+        }
+        match scan::matching_bracket(data, offset) {
+            Some(offset) => self.jump(offset),
+            None => self.message = Some("No matching bracket".to_string()),
+        }
+    }
+
+    /// Moves the cursor to the offset or ELF symbol given after the file name on the
+    /// command line. If neither is valid, the cursor is left at the start.
+    pub fn goto_start_arg(&mut self, arg: &str) {
+        if self.is_empty() {
+            return;
+        }
+        self.try_goto(0);
+        match parse_addr(arg) {
+            Some(addr) => match usize::try_from(addr) {
+                Ok(offset) if offset < self.cells.len() => {
+                    self.try_goto(offset);
+                }
+                _ => self.message = Some(format!("Offset {:#x} is out of range", addr)),
+            },
+            None => self.goto_symbol(arg),
+        }
+    }
+
+    /// Jumps to the file offset of the ELF symbol `name`.
+    pub fn goto_symbol(&mut self, name: &str) {
+        let elf = if let Some(elf) = &self.elf {
+            elf
+        } else {
+            self.message = Some("Not an ELF file".to_string());
+            return;
+        };
+        let symbol = if let Some(symbol) = elf.symbol(name) {
+            symbol
+        } else {
+            self.message = Some(format!("Unknown symbol \"{}\"", name));
+            return;
+        };
+        match elf.va_to_offset(symbol.value) {
+            Some(offset) if offset < self.cells.len() => {
+                self.jump(offset);
+            }
+            _ => {
+                self.message = Some(format!(
+                    "Symbol address {:#x} is not mapped to the file",
+                    symbol.value
+                ));
+            }
+        }
+    }
+
+    pub fn show_sections(&mut self) {
+        let entries = if let Some(elf) = &self.elf {
+            elf.sections
+                .iter()
+                .filter(|s| s.has_data() && s.size > 0 && (s.offset as usize) < self.cells.len())
+                .map(|s| {
+                    let range = format!("{:#x}..{:#x}", s.offset, s.offset + s.size);
+                    (s.offset as usize, format!("{:<24} {}", s.name, range))
+                })
+                .collect()
+        } else {
+            self.message = Some("Not an ELF file".to_string());
+            return;
+        };
+        self.show_results("Sections".to_string(), entries);
+    }
+
+    /// Returns to the offset the last followed pointer or branch was read from.
+    pub fn follow_back(&mut self) {
+        match self.back_stack.pop() {
+            Some(offset) => self.jump(offset),
+            None => self.message = Some("No pointer to return from".to_string()),
+        }
+    }
+
+    /// Formats the last hops of the followed pointers and branches, ending at the cursor.
+    fn breadcrumbs(&self) -> String {
+        const MAX_HOPS: usize = 4;
+        let skipped = self.back_stack.len().saturating_sub(MAX_HOPS);
+        let mut trail = if skipped > 0 { "… → " } else { "" }.to_string();
+        for offset in &self.back_stack[skipped..] {
+            trail += &format!("{:#x} → ", offset);
+        }
+        trail + &format!("{:#x}", self.offset())
+    }
+
+    /// Returns from a followed pointer and moves on to the next pointer-sized entry,
+    /// so that a pointer table can be walked with `p` and `]`.
+    pub fn next_table_entry(&mut self) {
+        if let Some(offset) = self.back_stack.pop() {
+            let next = offset + self.ptr_width.n_bytes();
+            if next + self.ptr_width.n_bytes() <= self.cells.len() {
+                self.try_goto(next);
+                self.set_width(self.ptr_width);
+            } else {
+                self.try_goto(offset);
+            }
+        }
+    }
+
+    pub fn type_cmd(&mut self, c: char) {
+        if c == '\n' {
+            if !self.cmd_buf.is_empty() {
+                self.last_cmd = self.cmd_buf.clone();
+            }
+            self.redraw_all();
+            let mut cmd = self.cmd_buf.trim().splitn(2, ' ').map(str::trim);
+            match cmd.next().unwrap() {
+                "q" => self.finished = true,
+                "split" => self.toggle_split(),
+                "grow" => match cmd.next().map(|arg| usize::from_str_radix(arg, 16)) {
+                    Some(Ok(n_bytes)) => self.grow(n_bytes),
+                    _ => self.message = Some("Usage: grow <hexbytes>".to_string()),
+                },
+                _ if self.is_empty() => {
+                    self.message = Some("Buffer is empty".to_string());
+                }
+                "w" => self.write(),
+                "flush" => self.flush(),
+                "diskdiff" => self.diff_disk(),
+                "freq" => self.show_frequencies(),
+                "align" => match cmd.next().map(|arg| arg.parse::<usize>()) {
+                    Some(Ok(alignment)) if alignment > 0 => self.align_cursor(alignment, true),
+                    _ => self.message = Some("Usage: align <bytes>".to_string()),
+                },
+                name @ ("strings" | "wstrings") => {
+                    let utf16 = name == "wstrings";
+                    match cmd.next().map(|arg| arg.parse::<usize>()) {
+                        None => self.find_strings(4, utf16),
+                        Some(Ok(min_len)) if min_len > 0 => self.find_strings(min_len, utf16),
+                        _ => self.message = Some("Usage: strings <minlen>".to_string()),
+                    }
+                }
+                "set" => match cmd.next() {
+                    Some(arg) => {
+                        let arg = arg.to_string();
+                        self.set_option(&arg);
+                    }
+                    None => self.message = Some("Usage: set <option>=<value>".to_string()),
+                },
+                "default" => match self.cells.default().with_names(cmd.next().unwrap_or("")) {
+                    Some(cell) => self.set_default_cell(cell),
+                    None => {
+                        self.message = Some("Usage: default <width> <format> <endian>".to_string())
+                    }
+                },
+                "alt" => match Cell::new_hex(0).with_names(cmd.next().unwrap_or("")) {
+                    Some(cell) => self.set_alt_cell(cell),
+                    None => self.message = Some("Usage: alt <width> <format> <endian>".to_string()),
+                },
+                "dual" => match cmd.next() {
+                    None => self.set_dual(None),
+                    Some("off") => self.dual = false,
+                    Some(name) => match Format::from_name(name) {
+                        Some(format) => self.set_dual(Some(format)),
+                        None => self.message = Some("Usage: dual [<format>|off]".to_string()),
+                    },
+                },
+                "stride" => {
+                    let args: Vec<_> = cmd.next().unwrap_or("").split_whitespace().collect();
+                    let args: Option<Vec<_>> = args.iter().map(|&arg| parse_addr(arg)).collect();
+                    match args.as_deref() {
+                        Some(&[n_bytes, count]) if n_bytes > 0 => {
+                            self.replicate_record(clamp_to_usize(n_bytes), clamp_to_usize(count))
+                        }
+                        _ => self.message = Some("Usage: stride <bytes> <count>".to_string()),
+                    }
+                }
+                "goval" => self.goto_value(),
+                "array" => {
+                    let args = cmd.next().unwrap_or("");
+                    let (names, count) = args.rsplit_once(' ').unwrap_or(("", args));
+                    let cell = Cell::new_hex(0).with_names(names);
+                    match (cell, count.parse()) {
+                        (Some(cell), Ok(count)) => self.show_array(cell, count),
+                        _ => {
+                            self.message = Some("Usage: array <width> <format> <count>".to_string())
+                        }
+                    }
+                }
+                "lenblob" => {
+                    let args = cmd.next().unwrap_or("");
+                    let (names, includes_prefix) = match args.strip_suffix("incl") {
+                        Some(names) => (names, true),
+                        None => (args, false),
+                    };
+                    match Cell::new_hex(0).with_names(names) {
+                        Some(cell) if !names.trim().is_empty() => {
+                            self.select_length_blob(cell, includes_prefix)
+                        }
+                        _ => {
+                            self.message =
+                                Some("Usage: lenblob <width> [<endian>] [incl]".to_string())
+                        }
+                    }
+                }
+                "charset" => match cmd.next().and_then(Charset::from_name) {
+                    Some(charset) => self.charset = charset,
+                    None => {
+                        self.message = Some("Usage: charset ascii|latin1|cp437|ebcdic".to_string())
+                    }
+                },
+                "nextfunc" => self.next_function(),
+                "entropy" => self.show_entropy(),
+                "cmp" => self.compare_clipboard(),
+                "swap" => {
+                    let args: Vec<_> = cmd.next().unwrap_or("").split_whitespace().collect();
+                    let args: Option<Vec<_>> = args.iter().map(|&arg| parse_addr(arg)).collect();
+                    match args.as_deref() {
+                        Some(&[a, b, len]) => self.swap_ranges(
+                            clamp_to_usize(a),
+                            clamp_to_usize(b),
+                            clamp_to_usize(len),
+                        ),
+                        _ => self.message = Some("Usage: swap <a> <b> <len>".to_string()),
+                    }
+                }
+                "vdiff" => {
+                    let args: Vec<_> = cmd.next().unwrap_or("").split_whitespace().collect();
+                    let args: Option<Vec<_>> = args.iter().map(|&arg| parse_addr(arg)).collect();
+                    match args.as_deref() {
+                        Some(&[a, b, len]) => self.diff_ranges(
+                            clamp_to_usize(a),
+                            clamp_to_usize(b),
+                            clamp_to_usize(len),
+                        ),
+                        _ => self.message = Some("Usage: vdiff <a> <b> <len>".to_string()),
+                    }
+                }
+                "reverse" => self.reverse_bytes(),
+                "swapnib" => self.swap_nibbles(),
+                "modified" => self.toggle_modified(),
+                "sep" => self.toggle_separators(),
+                "fixed" => self.toggle_fixed_width(),
+                "rel" => self.toggle_relative(),
+                "rules" => self.toggle_col_rules(),
+                "collapse" => self.toggle_collapse(),
+                "sym" => match cmd.next().map(str::trim) {
+                    Some(name) if !name.is_empty() => {
+                        let name = name.to_string();
+                        self.goto_symbol(&name);
+                    }
+                    _ => self.message = Some("Usage: sym <name>".to_string()),
+                },
+                "sections" => self.show_sections(),
+                "va" => self.toggle_va(),
+                "findval" => {
+                    let mut args = cmd.next().unwrap_or("").split_whitespace();
+                    let n_bytes = args.next().map(|arg| arg.parse::<usize>());
+                    let value = args
+                        .next()
+                        .map(|arg| u128::from_str_radix(arg.trim_start_matches("0x"), 16));
+                    match (n_bytes, value) {
+                        (Some(Ok(n_bytes)), Some(Ok(value))) => self.find_value(n_bytes, value),
+                        _ => self.message = Some("Usage: findval <bytes> <hexvalue>".to_string()),
+                    }
+                }
+                "lock" => {
+                    let args: Vec<_> = cmd.next().unwrap_or("").split_whitespace().collect();
+                    let args: Option<Vec<_>> = args.iter().map(|&arg| parse_addr(arg)).collect();
+                    match args.as_deref() {
+                        Some(&[]) => {
+                            let range = self
+                                .selection()
+                                .unwrap_or_else(|| self.cell_at_cursor().byte_range());
+                            self.lock(range)
+                        }
+                        Some(&[start, end]) => {
+                            self.lock(clamp_to_usize(start)..clamp_to_usize(end))
+                        }
+                        _ => self.message = Some("Usage: lock [<start> <end>]".to_string()),
+                    }
+                }
+                "unlock" => self.unlock_all(),
+                "checksum" => {
+                    let args: Vec<_> = cmd.next().unwrap_or("").split_whitespace().collect();
+                    let auto = args.last() == Some(&"auto");
+                    let args = &args[..args.len() - usize::from(auto)];
+                    let algorithm = args.first().and_then(|&name| Algorithm::from_name(name));
+                    let addrs: Option<Vec<_>> =
+                        args.iter().skip(1).map(|&arg| parse_addr(arg)).collect();
+                    match (algorithm, addrs.as_deref()) {
+                        (_, _) if args == ["off"] => {
+                            self.message =
+                                Some(format!("Removed {} checksums", self.checksums.len()));
+                            self.checksums.clear();
+                        }
+                        (Some(algorithm), Some(&[start, end, dst])) => self.add_checksum(
+                            algorithm,
+                            clamp_to_usize(start)..clamp_to_usize(end),
+                            clamp_to_usize(dst),
+                            auto,
+                        ),
+                        _ => {
+                            self.message = Some(
+                                "Usage: checksum crc32|sum8|sum16|inet <start> <end> <dst> [auto]"
+                                    .to_string(),
+                            )
+                        }
+                    }
+                }
+                "label" => {
+                    let text = cmd.next().unwrap_or("").to_string();
+                    self.set_label(&text);
+                }
+                "anomaly" => match cmd.next().and_then(parse_hex_bytes) {
+                    Some(pattern) if !pattern.is_empty() => self.find_anomalies(pattern),
+                    _ => self.message = Some("Usage: anomaly <hexpattern>".to_string()),
+                },
+                "ptrwidth" => match cmd.next().map(|arg| arg.parse::<usize>()) {
+                    Some(Ok(n_bytes)) => self.set_ptr_width(n_bytes),
+                    _ => self.message = Some("Usage: ptrwidth <bytes>".to_string()),
+                },
+                "repeat" => match cmd.next().map(|arg| arg.parse::<usize>()) {
+                    Some(Ok(count)) => self.repeat_bytes(count),
+                    _ => self.message = Some("Usage: repeat <count>".to_string()),
+                },
+                "read" => {
+                    let args = cmd.next().and_then(|args| args.rsplit_once(' '));
+                    match args.map(|(path, addr)| (path.trim().to_string(), parse_addr(addr))) {
+                        Some((path, Some(addr))) => {
+                            self.read_file(&path, addr.try_into().unwrap_or(usize::MAX))
+                        }
+                        _ => self.message = Some("Usage: read <path> <hexaddr>".to_string()),
+                    }
+                }
+                "report" => match cmd.next().map(str::to_string) {
+                    Some(path) if !path.is_empty() => self.write_report(&path),
+                    _ => self.message = Some("Usage: report <path>".to_string()),
+                },
+                "carve" => {
+                    let args = cmd.next().unwrap_or("");
+                    let mut words = args.splitn(3, ' ');
+                    let range = match (words.next().map(parse_addr), words.next().map(parse_addr)) {
+                        (Some(Some(start)), Some(Some(end))) => Some(
+                            start.try_into().unwrap_or(usize::MAX)
+                                ..end.try_into().unwrap_or(usize::MAX),
+                        ),
+                        _ => None,
+                    };
+                    let (range, path) = match (range, words.next()) {
+                        (Some(range), Some(path)) => (Some(range), path.trim()),
+                        _ => (self.selection(), args),
+                    };
+                    match range {
+                        Some(range) if !path.is_empty() => {
+                            let path = path.to_string();
+                            self.carve(range, &path);
+                        }
+                        _ => self.message = Some(
+                            "Usage: carve <path> (in visual mode) or carve <start> <end> <path>"
+                                .to_string(),
+                        ),
+                    }
+                }
+                "wb" => {
+                    let hex = cmd.next().unwrap_or("").to_string();
+                    self.write_hex(&hex);
+                }
+                "d" => match parse_count(cmd.next()) {
+                    Some(count) => {
+                        let addr = self.cell_at_cursor().offset;
+                        self.disasm_view
+                            .disassemble(addr, count, self.data_store.data());
+                    }
+                    _ => self.message = Some("Usage: d <count>".to_string()),
+                },
+                "line" => match cmd.next().map(str::parse) {
+                    Some(Ok(n)) => self.goto_line(n),
+                    _ => self.message = Some("Usage: line <n>".to_string()),
+                },
+                "base" => match cmd.next().and_then(parse_addr) {
+                    Some(base) if base <= u128::from(u64::MAX) => {
+                        self.disasm_view
+                            .set_base(base as u64, self.data_store.data());
+                        self.message = Some(format!("Disassembly base set to {:#x}", base));
+                    }
+                    _ => self.message = Some("Usage: base <hexaddr>".to_string()),
+                },
+                "di" => match cmd.next().map(str::parse::<isize>) {
+                    Some(Ok(n)) => self.move_insn(n),
+                    _ => self.message = Some("Usage: di <+/-n>".to_string()),
+                },
+                #[cfg(feature = "keystone")]
+                "asm" => match cmd.next() {
+                    Some(text) => {
+                        let text = text.to_string();
+                        self.patch_insn(&text);
+                    }
+                    None => self.message = Some("Usage: asm <instruction>".to_string()),
+                },
+                "db" => match cmd.next().map(|arg| usize::from_str_radix(arg, 16)) {
+                    Some(Ok(len)) => {
+                        let addr = self.cell_at_cursor().offset;
+                        self.disasm_view
+                            .disassemble_bytes(addr, len, self.data_store.data());
+                    }
+                    _ => self.message = Some("Usage: db <hexbytes>".to_string()),
+                },
+                cmd => {
+                    if let Some(addr) = parse_addr(cmd) {
+                        let offset = self.addr_to_offset(addr);
+                        match offset {
+                            Some(offset) => {
+                                self.jump(offset);
+                            }
+                            None if self.show_va => {
+                                self.message = Some(format!("Address {:#x} is not mapped", addr))
+                            }
+                            None => {
+                                self.message = Some(format!("Offset {:#x} is out of range", addr))
+                            }
+                        }
+                    } else if cmd.starts_with(|c: char| c.is_ascii_digit()) {
+                        self.message = Some(format!("Invalid address: \"{}\"", cmd));
+                    } else {
+                        self.message = Some(format!("Unknown command: \"{}\"", cmd))
+                    }
+                }
+            }
+            self.cmd_buf.clear();
+            self.cmd_selection = None;
+            // a command may leave a selection of its own
+            if self.mode == EditorMode::Command {
+                self.mode = EditorMode::Normal;
+            }
+        } else if c == '\x08' {
+            self.cmd_buf.pop();
+        } else {
+            self.cmd_buf.push(c);
+        }
+    }
+
+    /// Sets an option given as `name=value`.
+    fn set_option(&mut self, arg: &str) {
+        let (name, value) = arg.split_once('=').unwrap_or((arg, ""));
+        match name.trim() {
+            "scrolloff" => match value.trim().parse() {
+                Ok(scrolloff) => {
+                    self.scrolloff = scrolloff;
+                    self.scroll_to_cursor();
+                }
+                Err(_) => self.message = Some("Usage: set scrolloff=<lines>".to_string()),
+            },
+            "pager" => match value.trim() {
+                "on" | "" => self.set_pager(true),
+                "off" => self.set_pager(false),
+                _ => self.message = Some("Usage: set pager=on|off".to_string()),
+            },
+            "compactchar" => match value.trim() {
+                "on" => self.compact_char = true,
+                "off" => self.compact_char = false,
+                _ => self.message = Some("Usage: set compactchar=on|off".to_string()),
+            },
+            "wrap" => match value.trim() {
+                "on" => self.wrap = true,
+                "off" => self.wrap = false,
+                _ => self.message = Some("Usage: set wrap=on|off".to_string()),
+            },
+            name => self.message = Some(format!("Unknown option: \"{}\"", name)),
+        }
+    }
+
+    /// Attaches `text` to the cursor cell, or removes its label if `text` is empty.
+    pub fn set_label(&mut self, text: &str) {
+        let offset = self.cell_at_cursor().base_offset();
+        if text.is_empty() {
+            self.labels.remove(&offset);
+        } else {
+            self.labels.insert(offset, text.to_string());
+        }
+    }
+
+    /// Returns the labels of the cells starting in `range`, in order.
+    fn labels_in(&self, range: Range<usize>) -> Vec<&str> {
+        let mut labels: Vec<_> = self
+            .labels
+            .iter()
+            .filter(|(offset, _)| range.contains(offset))
+            .collect();
+        labels.sort_by_key(|(&offset, _)| offset);
+        labels
+            .into_iter()
+            .map(|(_, label)| label.as_str())
+            .collect()
+    }
+
+    /// Runs the `:` commands in `path`, one per line. Empty lines and lines starting with
+    /// `#` are skipped. A failing command does not stop the script; the first message is
+    /// shown with its line number, along with the number of further messages.
+    pub fn run_script(&mut self, path: &str) {
+        let script = match fs::read_to_string(path) {
+            Ok(script) => script,
+            Err(err) => {
+                self.message = Some(format!("Cannot read {}: {}", path, err));
+                return;
+            }
+        };
+        let mut messages = vec![];
+        for (i, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            self.set_mode(EditorMode::Command);
+            self.message = None;
+            self.cmd_buf = line.to_string();
+            self.type_cmd('\n');
+            if let Some(message) = self.message.take() {
+                messages.push(format!("{}:{}: {}", path, i + 1, message));
+            }
+            if self.finished {
+                break;
+            }
+        }
+        self.message = match messages.len() {
+            0 => None,
+            1 => messages.pop(),
+            n => Some(format!("{} (+{} more)", messages[0], n - 1)),
+        };
+    }
+
+    pub fn repeat_cmd(&mut self) {
+        if self.last_cmd.is_empty() {
+            return;
+        }
+        // cursor-relative arguments are evaluated against the current position
+        self.cmd_buf = self.last_cmd.clone();
+        self.type_cmd('\n');
+    }
+
+    fn draw_status_bar(&self) {
+        self.terminal
+            .goto(1, 1 + (PADDING_TOP + self.body_height) as u16);
+        if self.mode == EditorMode::Command {
+            write!(self.terminal, ":{}", self.cmd_buf);
+        } else if let Some(message) = &self.message {
+            write!(self.terminal, "{}", message);
+        } else if let Some(overlay) = &self.overlay {
+            let (selected, n_entries) = overlay.position();
+            write!(
+                self.terminal,
+                "Results {}/{} (Enter: jump, Esc: close)",
+                selected, n_entries
+            );
+        } else if self.is_empty() {
+            write!(self.terminal, "{:?} Empty buffer (0 bytes)", self.mode);
+        } else {
+            write!(self.terminal, "{}", self.format_status(&self.status_format));
+            if let Some((register, _)) = &self.recording {
+                write!(self.terminal, " recording @{}", register);
+            }
+            if self.ascii_focus {
+                write!(self.terminal, " [ascii]");
+            }
+            if self.is_ins() && self.cell_at_cursor().n_bytes() > 1 {
+                write!(self.terminal, " [{}]", self.ruler());
+            }
+            if !self.back_stack.is_empty() {
+                write_color!(self.terminal, Color::Null, " {}", self.breadcrumbs());
+            }
+            let offset = self.cell_at_cursor().base_offset();
+            if let Some(label) = self.labels.get(&offset) {
+                write_color!(self.terminal, Color::Ascii, " \"{}\"", label);
+            }
+        }
+        self.terminal.clear_line();
+    }
+
+    /// Reports the progress of a long operation in the status bar while it is running.
+    fn draw_progress(&self, what: &str, done: usize, total: usize) {
+        self.terminal
+            .goto(1, 1 + (PADDING_TOP + self.body_height) as u16);
+        let percent = done * 100 / max(total, 1);
+        write!(
+            self.terminal,
+            "{}... {}% (press any key to cancel)",
+            what, percent
+        );
+        self.terminal.clear_line();
+        self.terminal.flush();
+    }
+
+    /// Substitutes the `{field}` tokens in a status bar template.
+    fn format_status(&self, template: &str) -> String {
+        let cell = self.cell_at_cursor();
+        let mut status = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            status.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = match rest.find('}') {
+                Some(end) => end,
+                None => break,
+            };
+            let value = match &rest[1..end] {
+                "mode" => format!("{:?}", self.mode),
+                "x" => self.view.cursor_x.to_string(),
+                "y" => self.view.cursor_y.to_string(),
+                "offset" => format!("{:#018x}", cell.offset),
+                "format" => format!("{:?}", cell.format),
+                "width" => format!("{:?}", cell.width),
+                "byte_order" => format!("{:?}", cell.byte_order),
+                "percent" => (self.view.cursor_y * 100 / self.lines.len()).to_string(),
+                "size" => format!("{:#x}", self.cells.len()),
+                "dirty" => if self.dirty.is_some() { "[+]" } else { "" }.to_string(),
+                _ => rest[..=end].to_string(),
+            };
+            status.push_str(&value);
+            rest = &rest[end + 1..];
+        }
+        status.push_str(rest);
+        status
+    }
+
+    /// Draws a cell preceded by a space, or by a column rule if `rule` is set. A
+    /// `compact` cell is drawn as a single char with nothing in front of it.
+    fn draw_cell(
+        &self,
+        cell: &Cell,
+        selected: bool,
+        highlighted: bool,
+        min_cols: usize,
+        rule: bool,
+        compact: bool,
+    ) {
+        let data = &self.data_store.data()[cell.offset..];
+        assert!(data.len() >= cell.n_bytes());
+        if !compact {
+            if rule {
+                write_color!(self.terminal, Color::Null, "{}", RULE);
+            } else {
+                write!(self.terminal, " ");
+            }
+        }
+
+        if selected {
+            self.terminal.bg_color(Color::Selected);
+        } else if highlighted {
+            self.terminal.bg_color(Color::Visual);
+        }
+
+        let cell_width = if compact {
+            1
+        } else {
+            max(cell.n_cols(), min_cols) * 3 - 1
+        };
+        let value = cell
+            .format(cell.parse_value(data))
+            .with_separators(self.separators)
+            .with_fixed_width(self.fixed_width)
+            .with_charset(self.charset);
+
+        let fg_color = if self.is_locked(cell.byte_range()) {
+            Color::Locked
+        } else if self.show_modified && self.is_modified(cell.byte_range()) {
+            Color::Modified
+        } else if value.is_null() {
+            Color::Null
+        } else if value.is_ascii() {
+            Color::Ascii
+        } else {
+            Color::Default
+        };
+        self.terminal.fg_color(fg_color);
+
+        if cell.supports_cursor() && selected && self.is_ins() {
+            let (pre, cur, suf) = value.split(self.cursor_offset);
+            let w = cell.n_chars();
+
+            write!(self.terminal, "{:1$}", "", cell_width - w);
+            if let Some(pre) = pre {
+                write!(self.terminal, "{:1$}", pre, self.cursor_offset);
+            }
+
+            self.terminal.fg_color(Color::Cursor);
+            write!(self.terminal, "{:1$}", cur, 1);
+            self.terminal.fg_color(fg_color);
+
+            if let Some(suf) = suf {
+                write!(self.terminal, "{:1$}", suf, w - self.cursor_offset - 1);
+            }
+        } else {
+            write!(self.terminal, "{:1$}", value, cell_width);
+        }
+
+        self.terminal.reset_color();
+    }
+
+    fn draw_header(&self, padding: usize) {
+        self.terminal.goto(1, 1);
+        write!(self.terminal, "{0:1$}", "", padding);
+        self.clip_h_scroll();
+        let cpb = self.header_cpb();
+        for i in 0..(self.n_cols / cpb) {
+            if self.is_rule_col(i * cpb) {
+                write_color!(self.terminal, Color::Null, "{}", RULE);
+            } else {
+                write!(self.terminal, " ");
+            }
+            if self.view.cursor_x / cpb == i {
+                write_color!(
+                    self.terminal,
+                    Color::Selected,
+                    "{1:2$}{:02x}",
+                    i,
+                    "",
+                    (cpb - 1) * 3
+                );
+            } else {
+                write!(self.terminal, "{1:2$}{:02x}", i, "", (cpb - 1) * 3);
+            }
+        }
+        self.terminal.clear_line();
+    }
+
+    /// Drops the columns of the current row that are scrolled out of view. A space
+    /// is kept after the offsets, as a cell may be cut off in the middle.
+    fn clip_h_scroll(&self) {
+        if self.h_scroll > 0 {
+            write!(self.terminal, " ");
+            self.terminal.clip_left(self.h_scroll + 1);
+        } else {
+            self.terminal.clip_left(0);
+        }
+    }
+
+    /// Returns whether a column rule is drawn in front of column `col`. Rules are at
+    /// multiples of 8 columns, which are byte boundaries for every columns per byte.
+    fn is_rule_col(&self, col: usize) -> bool {
+        self.col_rules && col > 0 && col.is_multiple_of(RULE_INTERVAL)
+    }
+
+    /// Returns the columns per byte of the cursor line, which the header is laid out for.
+    fn header_cpb(&self) -> usize {
+        self.lines[self.view.cursor_y].cpb
+    }
+
+    /// Draws the offset of a line, or its distance from `cursor_line` in relative mode.
+    /// Offsets of lines whose layout does not match the header are dimmed, as the column
+    /// numbers do not apply to them.
+    fn draw_offset(&self, selected: bool, mismatched: bool, offset: usize, cursor_line: usize) {
+        if self.relative && !selected {
+            let distance = if offset < cursor_line {
+                format!("-{:#x}", cursor_line - offset)
+            } else {
+                format!("+{:#x}", offset - cursor_line)
+            };
+            let color = if mismatched {
+                Color::Null
+            } else {
+                Color::Default
+            };
+            write_color!(self.terminal, color, "{:>18}", distance);
+            return;
+        }
+        let va = match &self.elf {
+            Some(elf) if self.show_va => Some(elf.offset_to_va(offset)),
+            _ => None,
+        };
+        if let Some(va) = va {
+            let color = if selected {
+                Color::Selected
+            } else {
+                Color::Null
+            };
+            match va {
+                Some(va) => write_color!(self.terminal, color, "{:#018x}", va),
+                None => write_color!(self.terminal, color, "{:>18}", "??"),
+            }
+        } else if selected {
+            write_color!(self.terminal, Color::Selected, "{:#018x}", offset);
+        } else if mismatched {
+            write_color!(self.terminal, Color::Null, "{:#018x}", offset);
+        } else {
+            write!(self.terminal, "{:#018x}", offset);
+        }
+    }
+
+    /// Draws the values of the cells in `range` in the dual format, dimmed and cut off
+    /// at the right margin.
+    fn draw_line_dual(&self, range: Range<usize>) {
+        let data = self.data_store.data();
+        let mut values = vec![];
+        let mut offset = range.start;
+        while offset < range.end {
+            let cell = self.cells.get(offset);
+            let dual = Cell {
+                format: self.dual_format,
+                ..cell
+            };
+            let value = dual
+                .format(dual.parse_value(&data[cell.offset..]))
+                .with_separators(self.separators)
+                .with_charset(self.charset);
+            values.push(value.to_string().trim().to_string());
+            offset += cell.n_bytes();
+        }
+        let margin = self
+            .width
+            .saturating_sub(PADDING_LEFT + 3 * self.n_cols + 4);
+        let values: String = values.join(" ").chars().take(margin).collect();
+        if !values.is_empty() {
+            write_color!(self.terminal, Color::Null, " {}", values);
+        }
+    }
+
+    fn draw_separator(&self, row: usize) {
+        self.terminal.goto(1, 1 + (PADDING_TOP + row) as u16);
+        let width = PADDING_LEFT + self.n_cols * 3;
+        write_color!(self.terminal, Color::Null, "{}", "─".repeat(width));
+        self.terminal.clear_line();
+    }
+
+    fn draw_scrollbar(&self, view: &Viewport) {
+        let n_lines = self.lines.len();
+        let thumb_len = max(1, min(view.height, view.height * view.height / n_lines));
+        let thumb_start = min(view.scroll * view.height / n_lines, view.height - thumb_len);
+
+        for row in 0..view.height {
+            self.terminal
+                .goto(self.width as u16, 1 + (PADDING_TOP + view.top + row) as u16);
+            if (thumb_start..thumb_start + thumb_len).contains(&row) {
+                write!(self.terminal, "█");
+            } else {
+                write_color!(self.terminal, Color::Null, "│");
+            }
+        }
+    }
+
+    /// Makes the next draw format every line of the panes again.
+    pub fn redraw_all(&mut self) {
+        self.redraw = Redraw::All;
+    }
+
+    /// Makes the next draw format the lines holding the bytes in `range` again.
+    fn redraw_range(&mut self, range: Range<usize>) {
+        let first = self
+            .lines
+            .partition_point(|line| line.cell_range().end <= range.start);
+        let end = self.lines.partition_point(|line| line.offset < range.end);
+        (first..end).for_each(|i| self.redraw.add(i));
+    }
+
+    fn frame(&self) -> Frame {
+        let rows = iter::once(&self.view)
+            .chain(&self.split)
+            .map(|view| self.visible_rows(view))
+            .collect();
+        Frame {
+            views: (
+                Viewport {
+                    cursor_x: 0,
+                    cursor_y: 0,
+                    ..self.view
+                },
+                self.split,
+            ),
+            rows,
+            mode: self.mode,
+            ascii_focus: self.ascii_focus,
+            selection: self.selection(),
+            header_cpb: self.header_cpb(),
+            h_scroll: self.h_scroll,
+            insn_at_cursor: self.disasm_view.index_at(self.offset()),
+            relative_to: Some(self.lines[self.view.cursor_y].offset).filter(|_| self.relative),
+        }
+    }
+
+    /// Returns the lines to draw, which are all of them unless the frame is the same as
+    /// in the last draw, and remembers the frame.
+    fn take_redraw(&mut self) -> Redraw {
+        let frame = self.frame();
+        let cursor_y = self.view.cursor_y;
+        let mut redraw = mem::replace(&mut self.redraw, Redraw::Lines(BTreeSet::new()));
+        match self.drawn.take() {
+            Some((drawn, drawn_cursor_y)) if drawn == frame => {
+                redraw.add(drawn_cursor_y);
+                redraw.add(cursor_y);
+            }
+            _ => redraw = Redraw::All,
+        }
+        self.drawn = Some((frame, cursor_y));
+        redraw
+    }
+
+    fn draw_pane(&mut self, view: Viewport, redraw: &Redraw) {
+        let selection = self.selection();
+        let rows = self.visible_rows(&view);
+        let cursor_offset = self.cell_at_col(view.cursor_y, view.cursor_x).offset;
+        let mut insn_idx = self.disasm_view.first_at(self.lines[view.scroll].offset);
+
+        for (row, &line_idx) in rows.iter().enumerate() {
+            let i = match line_idx {
+                Some(i) if !redraw.includes(i) => {
+                    // the row stays as it is on the screen, and the disasm column goes on
+                    // with the next instruction all the same
+                    if !self.dual {
+                        insn_idx = insn_idx.map(|idx| idx + 1);
+                    }
+                    continue;
+                }
+                Some(i) => i,
+                None => {
+                    self.terminal
+                        .goto(1, 1 + (PADDING_TOP + view.top + row) as u16);
+                    write_color!(self.terminal, Color::Null, "*");
+                    self.terminal.clear_line();
+                    continue;
+                }
+            };
+            self.terminal
+                .goto(1, 1 + (PADDING_TOP + view.top + row) as u16);
+            let mut offset = self.lines[i].offset;
+            let mismatched = self.lines[i].cpb != self.header_cpb();
+            let cursor_line = self.lines[view.cursor_y].offset;
+            let at_cursor = !self.pager && view.cursor_y == i;
+            self.draw_offset(at_cursor, mismatched, offset, cursor_line);
+            self.clip_h_scroll();
+
+            /*
+            let bi = match self.lines[i].buddy {
+                Buddy::Above => "^",
+                Buddy::Below => "v",
+                Buddy::None => "-",
+            };
+            write!(self.terminal, " {}{}{}{}",
+                                    self.lines[i].min_cpb,
+                                    self.lines[i].cpb,
+                                    self.lines[i].level,
+                                    bi);
+             */
 
             let mut col = 0;
+            // the columns freed by compact char cells, filled in after their run
+            let mut compact_pad = 0;
             while col < self.n_cols && offset < self.cells.len() {
                 assert_eq!(self.lines[i].offset_to_col(offset), col);
                 assert_eq!(self.lines[i].col_to_offset(col), offset);
 
                 let cell = self.cells.get(offset);
                 let n_cols = max(cell.n_cols(), self.lines[i].cpb * cell.n_bytes());
-                let selected =
-                    self.cursor_y == i && col <= self.cursor_x && self.cursor_x < col + n_cols;
+                let selected = at_cursor && col <= view.cursor_x && view.cursor_x < col + n_cols;
+                let rule = self.is_rule_col(col);
                 col += n_cols;
 
                 assert!(col <= self.n_cols);
 
-                self.draw_cell(&cell, selected, self.lines[i].cpb * cell.n_bytes());
+                let highlighted = selection.as_ref().is_some_and(|r| r.contains(&offset));
+
+                let compact =
+                    self.compact_char && cell.format == Format::Char && cell.n_bytes() == 1;
+                if compact {
+                    if compact_pad == 0 {
+                        // a run is set off from what is in front of it
+                        write!(self.terminal, " ");
+                        compact_pad += n_cols * 3 - 2;
+                    } else {
+                        compact_pad += n_cols * 3 - 1;
+                    }
+                } else if compact_pad > 0 {
+                    write!(self.terminal, "{:1$}", "", compact_pad);
+                    compact_pad = 0;
+                }
+                self.draw_cell(
+                    &cell,
+                    selected,
+                    highlighted,
+                    self.lines[i].cpb * cell.n_bytes(),
+                    rule,
+                    compact,
+                );
                 offset += cell.n_bytes();
             }
+            write!(self.terminal, "{:1$}", "", compact_pad);
+            if offset == self.cells.len() {
+                write_color!(self.terminal, Color::Null, " ∎");
+            }
 
             if self.lines[i].len != offset - self.lines[i].offset {
                 eprintln!(
@@ -678,26 +3558,237 @@ impl<'d, W: Write> Editor<'d, W> {
                     offset - self.lines[i].offset
                 )
             }
-            //self.draw_line_ascii(self.lines[i].cell_range());
 
-            if self.disasm_view.is_enabled() {
-                let cursor_offset = self.cell_at_cursor().offset;
-                let relative_scroll = i as isize - self.cursor_y as isize;
-                if let Some(insn) = self.disasm_view.get(cursor_offset, relative_scroll) {
-                    if self.cursor_y == i {
-                        write_color!(self.terminal, Color::Selected, " {}", insn);
-                    } else {
-                        write!(self.terminal, " {}", insn);
-                    }
+            // the disasm column shows a continuous stream of instructions, starting with
+            // the one at the first byte of the pane
+            if self.dual {
+                self.draw_line_dual(self.lines[i].cell_range());
+            } else if let Some((range, spans)) = insn_idx.and_then(|idx| self.disasm_view.get(idx))
+            {
+                let at_cursor = range.contains(&cursor_offset);
+                write!(self.terminal, " ");
+                for (token, text) in spans {
+                    let color = match token {
+                        Token::Address if at_cursor => Color::Selected,
+                        Token::Address => Color::Null,
+                        Token::Mnemonic => Color::Mnemonic,
+                        Token::Register => Color::Register,
+                        Token::Immediate => Color::Immediate,
+                        Token::Other => Color::Default,
+                    };
+                    write_color!(self.terminal, color, "{}", text);
+                }
+                insn_idx = insn_idx.map(|idx| idx + 1);
+            } else if !self.labels.is_empty() {
+                // labels go into the right margin, as far as it is wide
+                let labels = self.labels_in(self.lines[i].cell_range()).join(", ");
+                let margin = self
+                    .width
+                    .saturating_sub(PADDING_LEFT + 3 * self.n_cols + 4);
+                if !labels.is_empty() && margin > 0 {
+                    let labels: String = labels.chars().take(margin).collect();
+                    write_color!(self.terminal, Color::Null, " # {}", labels);
                 }
             }
 
+            self.content_width = max(self.content_width, self.terminal.clipped_cols());
+            self.terminal.clear_line();
+        }
+
+        // clear rows left over from a previous, taller layout
+        for row in rows.len()..view.height {
+            self.terminal
+                .goto(1, 1 + (PADDING_TOP + view.top + row) as u16);
             self.terminal.clear_line();
+        }
+
+        self.draw_scrollbar(&view);
+    }
+
+    fn draw_body(&mut self, redraw: &Redraw) {
+        self.draw_header(PADDING_LEFT);
+        self.draw_pane(self.view, redraw);
+
+        if let Some(other) = self.split {
+            let max_cursor_y = self.lines.len() - 1;
+            self.draw_pane(
+                Viewport {
+                    scroll: min(other.scroll, max_cursor_y),
+                    cursor_y: min(other.cursor_y, max_cursor_y),
+                    ..other
+                },
+                redraw,
+            );
+            self.draw_separator(max(self.view.top, other.top) - 1);
+        }
+    }
 
-            i += 1;
+    pub fn draw(&mut self) {
+        if let Some(overlay) = &self.overlay {
+            let height = PADDING_TOP + self.body_height;
+            overlay.draw(&self.terminal, 1, height, self.width);
+            // the overlay covers the panes, which are drawn in full once it is closed
+            self.drawn = None;
+        } else if !self.is_empty() {
+            let redraw = self.take_redraw();
+            if redraw == Redraw::All {
+                self.content_width = 0;
+            }
+            self.draw_body(&redraw);
+            // the lines may have become narrower than what they are scrolled by
+            if self.h_scroll > self.max_h_scroll() {
+                self.h_scroll = self.max_h_scroll();
+                self.draw_body(&Redraw::All);
+                if let Some((frame, _)) = &mut self.drawn {
+                    frame.h_scroll = self.h_scroll;
+                }
+            }
         }
 
         self.draw_status_bar();
+        self.message = None; // only shown until the next redraw
         self.terminal.flush();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cell_index_at_col_in_empty_buffer() {
+        let mut data_store = DataStore::anon(0).unwrap();
+        let editor = Editor::new(&mut data_store, vec![], 130, 24);
+        assert!(editor.cells.is_empty());
+        assert_eq!(editor.cell_index_at_col(0, 0), 0);
+        assert_eq!(editor.cell_index_at_col(3, 7), 0);
+    }
+
+    #[test]
+    fn cell_index_at_col_clamps_to_last_cell() {
+        let mut data_store = DataStore::anon(3).unwrap();
+        let editor = Editor::new(&mut data_store, vec![], 130, 24);
+        assert_eq!(editor.cell_index_at_col(0, 7), 2);
+    }
+
+    fn annotate(cells: &mut SparseCells, offset: usize, format: Format, width: Width) {
+        for i in offset..offset + width.n_bytes() {
+            *cells.get_mut(i) = Cell::new(i, format, width, ByteOrder::LittleEndian);
+        }
+    }
+
+    #[test]
+    fn replicate_record_copies_the_template() {
+        // room for 3 records of 8 bytes, but not for a 4th
+        let mut data_store = DataStore::anon(28).unwrap();
+        let mut editor = Editor::new(&mut data_store, vec![], 130, 24);
+        annotate(&mut editor.cells, 0, Format::UDec, Width::HWord16);
+        annotate(&mut editor.cells, 4, Format::SDec, Width::Word32);
+        // in the way of the second record
+        annotate(&mut editor.cells, 8, Format::Oct, Width::Word32);
+        editor.replicate_record(8, 4);
+
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Copied annotations to 2 records of 0x8 bytes")
+        );
+        for k in 0..3 {
+            let cell = editor.cells.get(k * 8);
+            assert_eq!(
+                (cell.offset, cell.format, cell.width),
+                (k * 8, Format::UDec, Width::HWord16)
+            );
+            let cell = editor.cells.get(k * 8 + 4);
+            assert_eq!(
+                (cell.offset, cell.format, cell.width),
+                (k * 8 + 4, Format::SDec, Width::Word32)
+            );
+        }
+        assert!(!editor.cells.is_custom(10));
+        assert!(!editor.cells.is_custom(24));
+    }
+
+    #[test]
+    fn replicate_record_stops_at_overflowing_stride() {
+        let mut data_store = DataStore::anon(16).unwrap();
+        let mut editor = Editor::new(&mut data_store, vec![], 130, 24);
+        editor.replicate_record(usize::MAX, 3);
+        assert_eq!(
+            editor.message.as_deref(),
+            Some("Copied annotations to 0 records of 0xffffffffffffffff bytes")
+        );
+    }
+
+    #[test]
+    fn internet_checksum_is_stored_big_endian() {
+        let header =
+            b"\x45\x00\x00\x73\x00\x00\x40\x00\x40\x11\x00\x00\xc0\xa8\x00\x01\xc0\xa8\x00\xc7";
+        let mut data_store = DataStore::anon(header.len()).unwrap();
+        let mut editor = Editor::new(&mut data_store, vec![], 130, 24);
+        editor.write_bytes(0, header);
+        editor.add_checksum(Algorithm::Internet, 0..header.len(), 0xa, false);
+        assert_eq!(&editor.data_store.data()[0xa..0xc], b"\xb8\x61");
+    }
+
+    fn drawn_editor(data_store: &mut DataStore) -> Editor<'_, Vec<u8>> {
+        let mut editor = Editor::new(data_store, vec![], 130, 24);
+        editor.draw();
+        editor
+    }
+
+    #[test]
+    fn cursor_move_redraws_the_lines_left_and_entered() {
+        let mut data_store = DataStore::anon(0x1000).unwrap();
+        let mut editor = drawn_editor(&mut data_store);
+        editor.move_cursor_y(1);
+        assert_eq!(editor.take_redraw(), Redraw::Lines([0, 1].into()));
+        assert_eq!(editor.take_redraw(), Redraw::Lines([1].into()));
+    }
+
+    #[test]
+    fn edit_redraws_the_lines_it_changed() {
+        let mut data_store = DataStore::anon(0x1000).unwrap();
+        let mut editor = drawn_editor(&mut data_store);
+        let n_cols = editor.n_cols;
+        editor.write_bytes(3 * n_cols - 1, &[1, 2]);
+        assert_eq!(editor.take_redraw(), Redraw::Lines([0, 2, 3].into()));
+    }
+
+    #[test]
+    fn scroll_redraws_everything() {
+        let mut data_store = DataStore::anon(0x1000).unwrap();
+        let mut editor = drawn_editor(&mut data_store);
+        editor.move_cursor_y(editor.view.height as isize);
+        assert_ne!(editor.view.scroll, 0);
+        assert_eq!(editor.take_redraw(), Redraw::All);
+    }
+
+    /// A line of 8 bytes with 1 column per byte, followed by one of 4 bytes with 2.
+    fn split_lines() -> (Line, Line) {
+        let narrow = Line::new(0, 8);
+        let mut wide = Line::new(8, 4);
+        wide.cpb = 2;
+        (narrow, wide)
+    }
+
+    #[test]
+    fn same_byte_across_lines_with_different_cpb() {
+        let cells = SparseCells::new(12);
+        let (narrow, wide) = split_lines();
+        // byte 1 of the wide line is in column 2, which is byte 2 of the narrow one
+        assert_eq!(narrow.col_of_same_byte(&wide, 9, &cells), 1);
+        assert_eq!(wide.col_of_same_byte(&narrow, 2, &cells), 4);
+        // the wide line is shorter, so the cursor lands on its last byte
+        assert_eq!(wide.col_of_same_byte(&narrow, 6, &cells), 6);
+    }
+
+    #[test]
+    fn same_byte_in_a_wide_cell() {
+        let mut cells = SparseCells::new(12);
+        for i in 8..12 {
+            cells.get_mut(i).width = Width::Word32;
+        }
+        let (narrow, wide) = split_lines();
+        assert_eq!(wide.col_of_same_byte(&narrow, 2, &cells), 0);
+    }
+}
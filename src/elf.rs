@@ -0,0 +1,239 @@
+use std::convert::TryInto;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_NOBITS: u32 = 8;
+const SHT_DYNSYM: u32 = 11;
+
+pub struct Section {
+    pub name: String,
+    pub addr: u64,
+    pub offset: u64,
+    pub size: u64,
+    kind: u32,
+}
+
+impl Section {
+    /// Returns whether the section occupies bytes in the file.
+    pub fn has_data(&self) -> bool {
+        self.kind != SHT_NOBITS
+    }
+}
+
+/// A loadable segment, mapping `filesz` bytes at `offset` in the file to `vaddr`.
+struct Segment {
+    offset: u64,
+    vaddr: u64,
+    filesz: u64,
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub value: u64,
+}
+
+/// The segment, section and symbol tables of an ELF file.
+pub struct Elf {
+    segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+    pub symbols: Vec<Symbol>,
+}
+
+/// Reads the fields of a 32 or 64-bit ELF file in either byte order.
+struct Reader<'a> {
+    data: &'a [u8],
+    is_64: bool,
+    little_endian: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: usize) -> Option<[u8; N]> {
+        let bytes = self.data.get(offset..offset.checked_add(N)?)?;
+        let mut bytes: [u8; N] = bytes.try_into().ok()?;
+        if !self.little_endian {
+            bytes.reverse();
+        }
+        Some(bytes)
+    }
+
+    fn u16(&self, offset: usize) -> Option<u16> {
+        self.bytes(offset).map(u16::from_le_bytes)
+    }
+
+    fn u32(&self, offset: usize) -> Option<u32> {
+        self.bytes(offset).map(u32::from_le_bytes)
+    }
+
+    fn u64(&self, offset: usize) -> Option<u64> {
+        self.bytes(offset).map(u64::from_le_bytes)
+    }
+
+    /// Reads an address or size field, which is 4 or 8 bytes wide depending on the class.
+    fn word(&self, offset: usize) -> Option<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    /// Reads a NUL-terminated string starting at `offset`.
+    fn str(&self, offset: usize) -> Option<String> {
+        let bytes = self.data.get(offset..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+impl Elf {
+    /// Parses the headers of `data`, returning `None` if it is not a valid ELF file.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(..4)? != b"\x7fELF" {
+            return None;
+        }
+        let r = Reader {
+            data,
+            is_64: *data.get(4)? == 2,
+            little_endian: *data.get(5)? == 1,
+        };
+
+        let (phoff, phentsize, phnum) = if r.is_64 {
+            (r.u64(0x20)?, r.u16(0x36)?, r.u16(0x38)?)
+        } else {
+            (u64::from(r.u32(0x1c)?), r.u16(0x2a)?, r.u16(0x2c)?)
+        };
+        let segments = (0..phnum)
+            .filter_map(|i| {
+                let h = (phoff as usize).checked_add(usize::from(i) * usize::from(phentsize))?;
+                let w = if r.is_64 { 8 } else { 4 };
+                let first = if r.is_64 { 8 } else { 4 }; // p_flags precedes p_offset in ELF64
+                if r.u32(h)? != PT_LOAD {
+                    return None;
+                }
+                Some(Segment {
+                    offset: r.word(h.checked_add(first)?)?,
+                    vaddr: r.word(h.checked_add(first + w)?)?,
+                    filesz: r.word(h.checked_add(first + 3 * w)?)?,
+                })
+            })
+            .collect();
+
+        let (shoff, shentsize, shnum, shstrndx) = if r.is_64 {
+            (r.u64(0x28)?, r.u16(0x3a)?, r.u16(0x3c)?, r.u16(0x3e)?)
+        } else {
+            (
+                u64::from(r.u32(0x20)?),
+                r.u16(0x2e)?,
+                r.u16(0x30)?,
+                r.u16(0x32)?,
+            )
+        };
+        let header = |i: u16| (shoff as usize).checked_add(usize::from(i) * usize::from(shentsize));
+
+        // (name index, type, addr, offset, size, link)
+        let raw_sections = (0..shnum)
+            .map(|i| {
+                let h = header(i)?;
+                let w = if r.is_64 { 8 } else { 4 };
+                Some((
+                    r.u32(h)?,
+                    r.u32(h.checked_add(4)?)?,
+                    r.word(h.checked_add(8 + w)?)?,
+                    r.word(h.checked_add(8 + 2 * w)?)?,
+                    r.word(h.checked_add(8 + 3 * w)?)?,
+                    r.u32(h.checked_add(8 + 4 * w)?)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        let shstrtab = raw_sections.get(shstrndx as usize).map_or(0, |s| s.3);
+        let sections = raw_sections
+            .iter()
+            .map(|&(name, kind, addr, offset, size, _)| Section {
+                name: shstrtab
+                    .checked_add(u64::from(name))
+                    .and_then(|name| r.str(name as usize))
+                    .unwrap_or_default(),
+                addr,
+                offset,
+                size,
+                kind,
+            })
+            .collect();
+
+        let mut symbols = vec![];
+        for &(_, kind, _, offset, size, link) in &raw_sections {
+            if kind != SHT_SYMTAB && kind != SHT_DYNSYM {
+                continue;
+            }
+            let strtab = match raw_sections.get(link as usize) {
+                Some(strtab) => strtab.3,
+                None => continue,
+            };
+            let entsize = if r.is_64 { 24 } else { 16 };
+            for i in 0..(size / entsize) {
+                let s = match offset.checked_add(i * entsize) {
+                    Some(s) => s as usize,
+                    None => break,
+                };
+                let (name, value, shndx) = if r.is_64 {
+                    (r.u32(s), r.u64(s + 8), r.u16(s + 6))
+                } else {
+                    (r.u32(s), r.u32(s + 4).map(u64::from), r.u16(s + 14))
+                };
+                let (name, value) = match (name, value, shndx) {
+                    (Some(name), Some(value), Some(shndx)) if name != 0 && shndx != 0 => {
+                        (name, value)
+                    }
+                    _ => continue,
+                };
+                let name = strtab
+                    .checked_add(u64::from(name))
+                    .and_then(|name| r.str(name as usize));
+                if let Some(name) = name {
+                    symbols.push(Symbol { name, value });
+                }
+            }
+        }
+
+        Some(Elf {
+            segments,
+            sections,
+            symbols,
+        })
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+
+    /// Translates a virtual address to a file offset via the segment it lies in, or the
+    /// section if the file has no program headers.
+    pub fn va_to_offset(&self, va: u64) -> Option<usize> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|s| s.vaddr <= va && va - s.vaddr < s.filesz);
+        if let Some(s) = segment {
+            return s
+                .offset
+                .checked_add(va - s.vaddr)
+                .map(|offset| offset as usize);
+        }
+        self.sections
+            .iter()
+            .filter(|s| s.has_data() && s.addr != 0)
+            .find(|s| s.addr <= va && va - s.addr < s.size)
+            .and_then(|s| s.offset.checked_add(va - s.addr))
+            .map(|offset| offset as usize)
+    }
+
+    /// Translates a file offset to the virtual address it is loaded at.
+    pub fn offset_to_va(&self, offset: usize) -> Option<u64> {
+        let offset = offset as u64;
+        self.segments
+            .iter()
+            .find(|s| s.offset <= offset && offset - s.offset < s.filesz)
+            .and_then(|s| s.vaddr.checked_add(offset - s.offset))
+    }
+}
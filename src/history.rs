@@ -0,0 +1,47 @@
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+fn state_file() -> Option<PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_dir.join("bloxberg").join("positions"))
+}
+
+fn read_entries(state_file: &Path) -> Vec<(usize, PathBuf)> {
+    let contents = fs::read_to_string(state_file).unwrap_or_default();
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut entry = line.splitn(2, ' ');
+            let offset = usize::from_str_radix(entry.next()?, 16).ok()?;
+            Some((offset, PathBuf::from(entry.next()?)))
+        })
+        .collect()
+}
+
+/// Returns the cursor offset stored for `path` by a previous session.
+pub fn load_offset(path: &Path) -> Option<usize> {
+    let path = path.canonicalize().ok()?;
+    read_entries(&state_file()?)
+        .into_iter()
+        .find(|(_, p)| *p == path)
+        .map(|(offset, _)| offset)
+}
+
+pub fn save_offset(path: &Path, offset: usize) -> io::Result<()> {
+    let path = path.canonicalize()?;
+    let state_file = state_file().ok_or_else(|| io::Error::other("no cache directory"))?;
+    let mut entries = read_entries(&state_file);
+    entries.retain(|(_, p)| *p != path);
+    entries.push((offset, path));
+
+    fs::create_dir_all(state_file.parent().unwrap())?;
+    let mut file = File::create(state_file)?;
+    for (offset, path) in entries {
+        writeln!(file, "{:x} {}", offset, path.display())?;
+    }
+    Ok(())
+}
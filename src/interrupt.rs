@@ -0,0 +1,38 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+#[derive(Default)]
+struct State {
+    busy: AtomicBool,
+    cancelled: AtomicBool,
+}
+
+/// Lets the input thread cancel a long-running operation on the main thread.
+#[derive(Clone, Default)]
+pub struct Interrupt(Arc<State>);
+
+impl Interrupt {
+    /// Marks the start of an operation that can be cancelled by pressing a key.
+    pub fn begin(&self) {
+        self.0.cancelled.store(false, Ordering::SeqCst);
+        self.0.busy.store(true, Ordering::SeqCst);
+    }
+
+    pub fn end(&self) {
+        self.0.busy.store(false, Ordering::SeqCst);
+    }
+
+    /// Cancels the running operation, if any. Returns whether the key that caused
+    /// the cancellation was consumed by it.
+    pub fn cancel(&self) -> bool {
+        if self.0.busy.load(Ordering::SeqCst) {
+            self.0.cancelled.store(true, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use termion::event::Key;
+
+use crate::cell::{Format, Width};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Action {
+    NormalMode,
+    CommandMode,
+    InsertMode,
+    VisualMode,
+    ToggleFocus,
+    MoveNext,
+    MovePrev,
+    MoveDown,
+    MoveUp,
+    PageDown,
+    PageUp,
+    ScrollLeft,
+    ScrollRight,
+    ScrollCursor,
+    GotoStart,
+    GotoEnd,
+    FieldStart,
+    JumpPrev,
+    AlignNext,
+    AlignPrev,
+    FollowPointer,
+    GotoValue,
+    NextTableEntry,
+    FollowBack,
+    FollowBranch,
+    #[cfg(feature = "keystone")]
+    EditInsn,
+    NextInsn,
+    PrevInsn,
+    FindNext,
+    FindPrev,
+    ExpandRun,
+    RecordMacro,
+    PlayMacro,
+    Yank,
+    Increment,
+    Decrement,
+    CopyStyle,
+    PasteStyle,
+    StructEnd,
+    MatchBracket,
+    ShowBytes,
+    NextFormat,
+    PrevFormat,
+    SetFormat(Format),
+    FormatString,
+    SwitchByteOrder,
+    ToggleAlt,
+    IncWidth,
+    DecWidth,
+    SetWidth(Width),
+    RepeatCommand,
+    SwitchPane,
+    Quit,
+}
+
+impl Action {
+    /// Returns whether the action only moves the cursor, which changes no more than the
+    /// line it leaves and the line it enters unless the view scrolls.
+    pub fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            Action::MoveNext
+                | Action::MovePrev
+                | Action::MoveDown
+                | Action::MoveUp
+                | Action::PageDown
+                | Action::PageUp
+                | Action::GotoStart
+                | Action::GotoEnd
+        )
+    }
+}
+
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("normal_mode", Action::NormalMode),
+    ("command_mode", Action::CommandMode),
+    ("insert_mode", Action::InsertMode),
+    ("visual_mode", Action::VisualMode),
+    ("toggle_focus", Action::ToggleFocus),
+    ("move_next", Action::MoveNext),
+    ("move_prev", Action::MovePrev),
+    ("move_down", Action::MoveDown),
+    ("move_up", Action::MoveUp),
+    ("page_down", Action::PageDown),
+    ("page_up", Action::PageUp),
+    ("scroll_left", Action::ScrollLeft),
+    ("scroll_right", Action::ScrollRight),
+    ("scroll_cursor", Action::ScrollCursor),
+    ("goto_start", Action::GotoStart),
+    ("goto_end", Action::GotoEnd),
+    ("field_start", Action::FieldStart),
+    ("jump_prev", Action::JumpPrev),
+    ("align_next", Action::AlignNext),
+    ("align_prev", Action::AlignPrev),
+    ("follow_pointer", Action::FollowPointer),
+    ("goto_value", Action::GotoValue),
+    ("next_table_entry", Action::NextTableEntry),
+    ("follow_back", Action::FollowBack),
+    ("follow_branch", Action::FollowBranch),
+    #[cfg(feature = "keystone")]
+    ("edit_insn", Action::EditInsn),
+    ("next_insn", Action::NextInsn),
+    ("prev_insn", Action::PrevInsn),
+    ("find_next", Action::FindNext),
+    ("find_prev", Action::FindPrev),
+    ("expand_run", Action::ExpandRun),
+    ("record_macro", Action::RecordMacro),
+    ("play_macro", Action::PlayMacro),
+    ("yank", Action::Yank),
+    ("increment", Action::Increment),
+    ("decrement", Action::Decrement),
+    ("copy_style", Action::CopyStyle),
+    ("paste_style", Action::PasteStyle),
+    ("struct_end", Action::StructEnd),
+    ("match_bracket", Action::MatchBracket),
+    ("show_bytes", Action::ShowBytes),
+    ("next_format", Action::NextFormat),
+    ("prev_format", Action::PrevFormat),
+    ("format_hex", Action::SetFormat(Format::Hex)),
+    ("format_udec", Action::SetFormat(Format::UDec)),
+    ("format_sdec", Action::SetFormat(Format::SDec)),
+    ("format_oct", Action::SetFormat(Format::Oct)),
+    ("format_bin", Action::SetFormat(Format::Bin)),
+    ("format_char", Action::SetFormat(Format::Char)),
+    ("format_float", Action::SetFormat(Format::Float)),
+    ("format_guid", Action::SetFormat(Format::Guid)),
+    ("format_string", Action::FormatString),
+    ("switch_byte_order", Action::SwitchByteOrder),
+    ("toggle_alt", Action::ToggleAlt),
+    ("inc_width", Action::IncWidth),
+    ("dec_width", Action::DecWidth),
+    ("width_byte", Action::SetWidth(Width::Byte8)),
+    ("width_hword", Action::SetWidth(Width::HWord16)),
+    ("width_word", Action::SetWidth(Width::Word32)),
+    ("width_dword", Action::SetWidth(Width::DWord64)),
+    ("width_qword", Action::SetWidth(Width::QWord128)),
+    ("width_address", Action::SetWidth(Width::ADDRESS)),
+    ("repeat_command", Action::RepeatCommand),
+    ("switch_pane", Action::SwitchPane),
+    ("quit", Action::Quit),
+];
+
+const DEFAULT_BINDINGS: &[(Key, Action)] = &[
+    (Key::Esc, Action::NormalMode),
+    (Key::Char(':'), Action::CommandMode),
+    (Key::Char('Q'), Action::Quit),
+    (Key::Char('q'), Action::RecordMacro),
+    (Key::Char('@'), Action::PlayMacro),
+    (Key::Char('y'), Action::Yank),
+    (Key::Ctrl('a'), Action::Increment),
+    (Key::Ctrl('x'), Action::Decrement),
+    (Key::Char('Y'), Action::CopyStyle),
+    (Key::Char('='), Action::PasteStyle),
+    (Key::Char('i'), Action::InsertMode),
+    (Key::Char('v'), Action::VisualMode),
+    (Key::Char('\t'), Action::ToggleFocus),
+    (Key::Right, Action::MoveNext),
+    (Key::Char('l'), Action::MoveNext),
+    (Key::Left, Action::MovePrev),
+    (Key::Char('h'), Action::MovePrev),
+    (Key::Down, Action::MoveDown),
+    (Key::Char('j'), Action::MoveDown),
+    (Key::Up, Action::MoveUp),
+    (Key::Char('k'), Action::MoveUp),
+    (Key::PageDown, Action::PageDown),
+    (Key::PageUp, Action::PageUp),
+    (Key::Alt('h'), Action::ScrollLeft),
+    (Key::Alt('l'), Action::ScrollRight),
+    (Key::Char('z'), Action::ScrollCursor),
+    (Key::Home, Action::GotoStart),
+    (Key::End, Action::GotoEnd),
+    (Key::Char('^'), Action::FieldStart),
+    (Key::Char('\''), Action::JumpPrev),
+    (Key::Char('}'), Action::AlignNext),
+    (Key::Char('{'), Action::AlignPrev),
+    (Key::Char('p'), Action::FollowPointer),
+    (Key::Char('P'), Action::GotoValue),
+    (Key::Char(']'), Action::NextTableEntry),
+    (Key::Ctrl('o'), Action::FollowBack),
+    (Key::Char('J'), Action::FollowBranch),
+    #[cfg(feature = "keystone")]
+    (Key::Char('E'), Action::EditInsn),
+    (Key::Alt('j'), Action::NextInsn),
+    (Key::Alt('k'), Action::PrevInsn),
+    (Key::Char('n'), Action::FindNext),
+    (Key::Char('N'), Action::FindPrev),
+    (Key::Char('o'), Action::ExpandRun),
+    (Key::Char(')'), Action::StructEnd),
+    (Key::Char('%'), Action::MatchBracket),
+    (Key::Char('r'), Action::ShowBytes),
+    (Key::Char('f'), Action::NextFormat),
+    (Key::Char('F'), Action::PrevFormat),
+    (Key::Char('x'), Action::SetFormat(Format::Hex)),
+    (Key::Char('d'), Action::SetFormat(Format::SDec)),
+    (Key::Char('u'), Action::SetFormat(Format::UDec)),
+    (Key::Char('t'), Action::SetFormat(Format::Bin)),
+    (Key::Char('c'), Action::SetFormat(Format::Char)),
+    (Key::Char('U'), Action::SetFormat(Format::Guid)),
+    (Key::Char('s'), Action::FormatString),
+    (Key::Char('e'), Action::SwitchByteOrder),
+    (Key::Char('A'), Action::ToggleAlt),
+    (Key::Char('+'), Action::IncWidth),
+    (Key::Char('-'), Action::DecWidth),
+    (Key::Char('b'), Action::SetWidth(Width::Byte8)),
+    (Key::Char('w'), Action::SetWidth(Width::Word32)),
+    (Key::Char('a'), Action::SetWidth(Width::ADDRESS)),
+    (Key::Char('.'), Action::RepeatCommand),
+    (Key::Ctrl('w'), Action::SwitchPane),
+];
+
+fn parse_action(name: &str) -> Option<Action> {
+    ACTION_NAMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, action)| *action)
+}
+
+/// Parses a key as written in the config file, e.g. `h`, `C-w`, `PageDown`.
+fn parse_key(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => return Some(Key::Char(c)),
+        (Some('C'), Some('-')) | (Some('A'), Some('-')) => {
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            return Some(if name.starts_with('C') {
+                Key::Ctrl(c)
+            } else {
+                Key::Alt(c)
+            });
+        }
+        _ => {}
+    }
+
+    match name {
+        "Esc" => Some(Key::Esc),
+        "Tab" => Some(Key::Char('\t')),
+        "Space" => Some(Key::Char(' ')),
+        "Backspace" => Some(Key::Backspace),
+        "Left" => Some(Key::Left),
+        "Right" => Some(Key::Right),
+        "Up" => Some(Key::Up),
+        "Down" => Some(Key::Down),
+        "Home" => Some(Key::Home),
+        "End" => Some(Key::End),
+        "PageUp" => Some(Key::PageUp),
+        "PageDown" => Some(Key::PageDown),
+        "Delete" => Some(Key::Delete),
+        "Insert" => Some(Key::Insert),
+        _ => None,
+    }
+}
+
+pub struct Keymap {
+    map: HashMap<Key, Action>,
+}
+
+impl Keymap {
+    /// Loads the default bindings, overridden by the bindings in `path` if it exists.
+    ///
+    /// Each line of the file has the form `<key> <action>`, where an action of `none`
+    /// removes the binding. Empty lines and lines starting with `#` are ignored.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Ok(keymap),
+        };
+
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg| format!("{}:{}: {}", path.display(), i + 1, msg);
+
+            let mut words = line.split_whitespace();
+            let (key, action) = match (words.next(), words.next(), words.next()) {
+                (Some(key), Some(action), None) => (key, action),
+                _ => return Err(error("expected \"<key> <action>\"")),
+            };
+            let key = parse_key(key).ok_or_else(|| error("unknown key"))?;
+            if action == "none" {
+                keymap.map.remove(&key);
+            } else {
+                let action = parse_action(action).ok_or_else(|| error("unknown action"))?;
+                keymap.map.insert(key, action);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn get(&self, key: &Key) -> Option<Action> {
+        self.map.get(key).cloned()
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            map: DEFAULT_BINDINGS.iter().cloned().collect(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cell::{ByteOrder, Cell, Format, Width};
+
+use ByteOrder::{BigEndian as BE, LittleEndian as LE};
+use Format::{Char, Hex, UDec};
+use Width::{Byte8, DWord64, HWord16, Word32};
+
+const fn field(offset: usize, format: Format, width: Width, byte_order: ByteOrder) -> Cell {
+    Cell::new(offset, format, width, byte_order)
+}
+
+const PNG: &[Cell] = &[
+    field(0x08, UDec, Word32, BE), // IHDR length
+    field(0x0c, Char, Byte8, BE),  // chunk type
+    field(0x0d, Char, Byte8, BE),
+    field(0x0e, Char, Byte8, BE),
+    field(0x0f, Char, Byte8, BE),
+    field(0x10, UDec, Word32, BE), // width
+    field(0x14, UDec, Word32, BE), // height
+    field(0x18, UDec, Byte8, BE),  // bit depth
+    field(0x19, UDec, Byte8, BE),  // color type
+];
+
+const GIF: &[Cell] = &[
+    field(0x06, UDec, HWord16, LE), // width
+    field(0x08, UDec, HWord16, LE), // height
+];
+
+// only the aligned fields of the local file header
+const ZIP: &[Cell] = &[
+    field(0x04, UDec, HWord16, LE), // version
+    field(0x06, Hex, HWord16, LE),  // flags
+    field(0x08, UDec, HWord16, LE), // compression method
+    field(0x0a, Hex, HWord16, LE),  // modification time
+    field(0x0c, Hex, HWord16, LE),  // modification date
+    field(0x1a, UDec, HWord16, LE), // file name length
+    field(0x1c, UDec, HWord16, LE), // extra field length
+];
+
+const ELF64: &[Cell] = &[
+    field(0x10, UDec, HWord16, LE), // e_type
+    field(0x12, UDec, HWord16, LE), // e_machine
+    field(0x14, UDec, Word32, LE),  // e_version
+    field(0x18, Hex, DWord64, LE),  // e_entry
+    field(0x20, Hex, DWord64, LE),  // e_phoff
+    field(0x28, Hex, DWord64, LE),  // e_shoff
+    field(0x30, Hex, Word32, LE),   // e_flags
+    field(0x34, UDec, HWord16, LE), // e_ehsize
+    field(0x36, UDec, HWord16, LE), // e_phentsize
+    field(0x38, UDec, HWord16, LE), // e_phnum
+    field(0x3a, UDec, HWord16, LE), // e_shentsize
+    field(0x3c, UDec, HWord16, LE), // e_shnum
+    field(0x3e, UDec, HWord16, LE), // e_shstrndx
+];
+
+const MAGIC: &[(&str, &[u8], &[Cell])] = &[
+    ("PNG", b"\x89PNG\r\n\x1a\n", PNG),
+    ("GIF", b"GIF8", GIF),
+    ("ZIP", b"PK\x03\x04", ZIP),
+    ("ELF64", b"\x7fELF\x02\x01", ELF64),
+];
+
+/// Recognizes the file format of `data` by its magic bytes and returns the name of the
+/// format and the cells of its header fields.
+pub fn detect(data: &[u8]) -> Option<(&'static str, &'static [Cell])> {
+    MAGIC
+        .iter()
+        .find(|(_, magic, _)| data.starts_with(magic))
+        .map(|&(name, _, fields)| (name, fields))
+}
@@ -1,95 +1,450 @@
-use std::fs::OpenOptions;
+use std::cmp::min;
+use std::fs::{self, OpenOptions};
 use std::io::{stdin, stdout, BufWriter, Write};
-use std::{env, io};
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+use std::{env, io, process, thread};
 use termion::event::{Event, Key, MouseButton, MouseEvent};
 use termion::input::{MouseTerminal, TermRead};
 use termion::raw::IntoRawMode;
 
-use crate::cell::{Format, Width};
+use crate::cell::{ByteOrder, Cell, Format, Width};
+use crate::config::Config;
 use crate::data_store::DataStore;
 use crate::editor::*;
+use crate::interrupt::Interrupt;
+use crate::keymap::{Action, Keymap};
+use crate::terminal::{PASTE_END, PASTE_START};
 
+#[cfg(any(feature = "keystone", test))]
+mod asm;
 mod data_store;
 #[macro_use]
 mod terminal;
 mod cell;
+mod charset;
+mod checksum;
+mod config;
 mod disasm;
 mod editor;
+mod elf;
+mod history;
+mod interrupt;
+mod keymap;
+mod magic;
+mod overlay;
+mod scan;
 mod util;
 
-fn handle_key<W: Write>(key: Key, editor: &mut Editor<W>) {
+struct Options {
+    target: Option<String>,
+    /// The offset or ELF symbol given after the file name, as in `file+0x100`.
+    start: Option<String>,
+    restore_cursor: bool,
+    journal: Option<PathBuf>,
+    commands: Option<String>,
+    read_only: bool,
+    magic: bool,
+    pager: bool,
+    color: bool,
+    default_cell: Option<Cell>,
+}
+
+impl Options {
+    fn parse() -> Self {
+        let mut options = Options {
+            target: None,
+            start: None,
+            restore_cursor: true,
+            journal: None,
+            commands: None,
+            read_only: false,
+            magic: true,
+            pager: false,
+            color: colors_supported(),
+            default_cell: None,
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--no-restore" => options.restore_cursor = false,
+                "--readonly" => options.read_only = true,
+                "--no-magic" => options.magic = false,
+                "--no-color" => options.color = false,
+                "--pager" => options.pager = true,
+                "--default" => {
+                    let names = args.next().unwrap_or_else(|| usage());
+                    let cell = Cell::new_hex(0).with_names(&names);
+                    options.default_cell = Some(cell.unwrap_or_else(|| usage()));
+                }
+                "--format" | "--width" | "--endian" => {
+                    let name = args.next().unwrap_or_else(|| usage());
+                    let mut cell = options.default_cell.unwrap_or_else(|| Cell::new_hex(0));
+                    match arg.as_str() {
+                        "--format" => {
+                            cell.format = Format::from_name(&name).unwrap_or_else(|| usage())
+                        }
+                        "--width" => {
+                            cell.width = Width::from_name(&name).unwrap_or_else(|| usage())
+                        }
+                        _ => {
+                            cell.byte_order = ByteOrder::from_name(&name).unwrap_or_else(|| usage())
+                        }
+                    }
+                    options.default_cell = Some(cell);
+                }
+                "--commands" => options.commands = Some(args.next().unwrap_or_else(|| usage())),
+                "--journal" => {
+                    options.journal = Some(args.next().unwrap_or_else(|| usage()).into())
+                }
+                _ if arg.starts_with("--") || options.target.is_some() => usage(),
+                _ => {
+                    let (target, start) = split_target(arg);
+                    options.target = Some(target);
+                    options.start = start;
+                }
+            }
+        }
+        options
+    }
+}
+
+/// Splits `file+<offset>`, `file:<offset>` or `file+<symbol>` into the file name and
+/// what follows it. Names of existing files are never split.
+fn split_target(arg: String) -> (String, Option<String>) {
+    if Path::new(&arg).exists() {
+        return (arg, None);
+    }
+    match arg.rfind(['+', ':']) {
+        Some(i) if i > 0 && Path::new(&arg[..i]).exists() => {
+            (arg[..i].to_string(), Some(arg[i + 1..].to_string()))
+        }
+        _ => (arg, None),
+    }
+}
+
+/// Follows the `NO_COLOR` convention and leaves out colors on dumb terminals or when
+/// stdout is not a terminal.
+fn colors_supported() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let dumb = env::var("TERM").is_ok_and(|term| term == "dumb");
+    !no_color && !dumb && termion::is_tty(&stdout())
+}
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: bloxberg [--no-restore] [--readonly] [--no-magic] [--no-color] [--pager] \
+         [--journal <path>] [--commands <path>] [--default <width>,<format>,<endian>] \
+         [--format <format>] [--width <width>] [--endian <endian>] \
+         [<file>[+<offset> | +<symbol>] | <n_bytes>]\n\n\
+         Press Q or type :q to quit. q records a macro."
+    );
+    process::exit(1);
+}
+
+/// How long the event loop waits for input before checking for other changes.
+const TICK: Duration = Duration::from_millis(200);
+
+fn handle_key<W: Write>(key: Key, editor: &mut Editor<W>, keymap: &Keymap) {
+    editor.record_key(key);
+    if let Some((action, count)) = editor.take_pending() {
+        if let Key::Char(register) = key {
+            handle_register(action, count, register, editor, keymap);
+        }
+        return;
+    }
+
     match key {
-        Key::Esc => editor.set_mode(EditorMode::Normal),
         Key::Char(c) if editor.is_cmd() => editor.type_cmd(c),
         Key::Backspace if editor.is_cmd() => editor.type_cmd('\x08'),
+        _ if editor.is_ins() && keymap.get(&key) == Some(Action::ToggleFocus) => {
+            editor.toggle_ascii_focus()
+        }
         Key::Char(c) if editor.is_ins() => editor.insert(c),
-        Key::Char(':') => editor.set_mode(EditorMode::Command),
-        Key::Char('i') => editor.set_mode(EditorMode::Insert),
-        Key::Right | Key::Char('l') => editor.move_cursor_next(),
-        Key::Left | Key::Char('h') => editor.move_cursor_prev(),
-        Key::Down | Key::Char('j') => editor.move_cursor_y(1),
-        Key::Up | Key::Char('k') => editor.move_cursor_y(-1),
-        Key::PageDown => editor.move_cursor_y(editor.height as isize),
-        Key::PageUp => editor.move_cursor_y(-(editor.height as isize)),
-        Key::Home => editor.set_cursor(0, 0),
-        Key::End => editor.set_cursor_end(),
-        Key::Char('p') => editor.follow_pointer(),
-        Key::Char('f') => editor.switch_format(false),
-        Key::Char('F') => editor.switch_format(true),
-        Key::Char('x') => editor.set_format(Format::Hex),
-        Key::Char('d') => editor.set_format(Format::SDec),
-        Key::Char('u') => editor.set_format(Format::UDec),
-        Key::Char('t') => editor.set_format(Format::Bin),
-        Key::Char('c') => editor.set_format(Format::Char),
-        Key::Char('s') => editor.format_string(),
-        Key::Char('e') => editor.switch_byte_order(),
-        Key::Char('+') => editor.inc_width(),
-        Key::Char('-') => editor.dec_width(),
-        Key::Char('b') => editor.set_width(Width::Byte8),
-        Key::Char('w') => editor.set_width(Width::Word32),
-        Key::Char('a') => editor.set_width(Width::ADDRESS),
-        Key::Char('q') => editor.finished = true,
+        _ if editor.has_overlay() => handle_overlay_key(key, editor, keymap),
+        _ => {
+            if let Some(action) = keymap.get(&key) {
+                let count = editor.take_count();
+                perform(action, count, editor);
+            } else if let Key::Char(c @ '0'..='9') = key {
+                editor.push_count_digit(c.to_digit(10).unwrap() as usize);
+            }
+        }
+    }
+}
+
+fn handle_register<W: Write>(
+    action: Action,
+    count: usize,
+    register: char,
+    editor: &mut Editor<W>,
+    keymap: &Keymap,
+) {
+    match action {
+        Action::RecordMacro => editor.start_recording(register),
+        Action::ScrollCursor => editor.scroll_cursor_to(register),
+        Action::JumpPrev if register == '\'' => editor.jump_prev(),
+        Action::PlayMacro => {
+            if let Some(keys) = editor.start_replay(register) {
+                'replay: for _ in 0..count {
+                    for &key in &keys {
+                        handle_key(key, editor, keymap);
+                        if editor.finished {
+                            break 'replay;
+                        }
+                    }
+                }
+                editor.end_replay();
+            }
+        }
         _ => {}
     }
 }
 
+fn handle_overlay_key<W: Write>(key: Key, editor: &mut Editor<W>, keymap: &Keymap) {
+    if key == Key::Char('\n') {
+        editor.overlay_select();
+        return;
+    }
+    match keymap.get(&key) {
+        Some(Action::MoveDown) => editor.overlay_move(1),
+        Some(Action::MoveUp) => editor.overlay_move(-1),
+        Some(Action::PageDown) => editor.overlay_move(editor.height() as isize),
+        Some(Action::PageUp) => editor.overlay_move(-(editor.height() as isize)),
+        Some(Action::NormalMode) | Some(Action::Quit) => editor.close_overlay(),
+        // q records a macro in the editor, but closes the overlay like a pager
+        _ if key == Key::Char('q') => editor.close_overlay(),
+        _ => {}
+    }
+}
+
+fn perform<W: Write>(action: Action, count: usize, editor: &mut Editor<W>) {
+    if !action.is_motion() {
+        editor.redraw_all();
+    }
+    match action {
+        Action::NormalMode => editor.set_mode(EditorMode::Normal),
+        Action::CommandMode => editor.set_mode(EditorMode::Command),
+        Action::Quit => editor.finished = true,
+        _ if editor.is_empty() => {}
+        Action::InsertMode => editor.set_mode(EditorMode::Insert),
+        Action::VisualMode => editor.set_mode(EditorMode::Visual),
+        Action::ToggleFocus => editor.toggle_ascii_focus(),
+        Action::MoveNext => (0..count).for_each(|_| editor.move_cursor_next()),
+        Action::MovePrev => (0..count).for_each(|_| editor.move_cursor_prev()),
+        Action::MoveDown => editor.move_cursor_y(count as isize),
+        Action::MoveUp => editor.move_cursor_y(-(count as isize)),
+        Action::PageDown => editor.move_cursor_y((count * editor.height()) as isize),
+        Action::PageUp => editor.move_cursor_y(-((count * editor.height()) as isize)),
+        Action::ScrollLeft => editor.scroll_h(-(count as isize)),
+        Action::ScrollRight => editor.scroll_h(count as isize),
+        Action::GotoStart => editor.goto_start(),
+        Action::GotoEnd => editor.goto_end(),
+        Action::FieldStart => editor.goto_field_start(),
+        Action::AlignNext => editor.align_cursor_to_width(true),
+        Action::AlignPrev => editor.align_cursor_to_width(false),
+        Action::FollowPointer => editor.follow_pointer(),
+        Action::GotoValue => editor.goto_value(),
+        Action::NextTableEntry => editor.next_table_entry(),
+        Action::FollowBack => editor.follow_back(),
+        Action::FollowBranch => editor.follow_branch(),
+        #[cfg(feature = "keystone")]
+        Action::EditInsn => editor.edit_insn(),
+        Action::NextInsn => editor.move_insn(count as isize),
+        Action::PrevInsn => editor.move_insn(-(count as isize)),
+        Action::FindNext => (0..count).for_each(|_| editor.find_next(true)),
+        Action::FindPrev => (0..count).for_each(|_| editor.find_next(false)),
+        Action::ExpandRun => editor.expand_run(),
+        Action::RecordMacro if editor.is_recording() => editor.stop_recording(),
+        Action::RecordMacro | Action::PlayMacro | Action::ScrollCursor | Action::JumpPrev => {
+            editor.set_pending(action, count)
+        }
+        Action::Yank => editor.yank(),
+        Action::ShowBytes => editor.show_bytes(),
+        Action::Increment => editor.add_to_value(count as i128),
+        Action::Decrement => editor.add_to_value(-(count as i128)),
+        Action::CopyStyle => editor.copy_style(),
+        Action::PasteStyle => editor.paste_style(),
+        Action::StructEnd => editor.goto_struct_end(),
+        Action::MatchBracket => editor.goto_matching_bracket(),
+        Action::NextFormat => editor.repeat_on_cells(count, |e| e.switch_format(false)),
+        Action::PrevFormat => editor.repeat_on_cells(count, |e| e.switch_format(true)),
+        Action::SetFormat(format) => editor.repeat_on_cells(count, |e| e.set_format(format)),
+        Action::FormatString => editor.format_string(),
+        Action::SwitchByteOrder => editor.repeat_on_cells(count, |e| e.switch_byte_order()),
+        Action::ToggleAlt => editor.toggle_alt(),
+        Action::IncWidth => editor.repeat_on_cells(count, |e| e.inc_width()),
+        Action::DecWidth => editor.repeat_on_cells(count, |e| e.dec_width()),
+        Action::SetWidth(width) => editor.repeat_on_cells(count, |e| e.set_width(width)),
+        Action::RepeatCommand => editor.repeat_cmd(),
+        Action::SwitchPane => editor.switch_pane(),
+    }
+}
+
 fn handle_mouse<W: Write>(me: MouseEvent, editor: &mut Editor<W>) {
-    eprintln!("{:?}", me);
     match me {
+        MouseEvent::Press(MouseButton::Left, x, y) => editor.click(x, y),
+        MouseEvent::Hold(x, y) => editor.drag(x, y),
         MouseEvent::Press(MouseButton::WheelUp, _, _) => editor.scroll(-1),
         MouseEvent::Press(MouseButton::WheelDown, _, _) => editor.scroll(1),
         _ => {}
     }
 }
 
+fn open(options: &Options) -> io::Result<(DataStore, Option<PathBuf>)> {
+    let arg = match &options.target {
+        Some(arg) => arg,
+        None => return Ok((DataStore::anon(1024)?, None)),
+    };
+    if let Ok(n_bytes) = arg.parse() {
+        return Ok((DataStore::anon(n_bytes)?, None));
+    }
+
+    let with_path = |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", arg, err));
+    // opening a fifo blocks until a writer shows up, so refuse it before getting that far
+    if fs::metadata(arg).is_ok_and(|metadata| metadata.file_type().is_fifo()) {
+        return Err(with_path(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot open a pipe",
+        )));
+    }
+    let data_store = if options.read_only {
+        let file = OpenOptions::new().read(true).open(arg).map_err(with_path)?;
+        DataStore::read_only(file).map_err(with_path)?
+    } else {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(arg)
+            .map_err(with_path)?;
+        DataStore::file(file).map_err(with_path)?
+    };
+    Ok((data_store, Some(PathBuf::from(arg))))
+}
+
 fn main() -> Result<(), io::Error> {
-    let mut data_store = if let Some(arg) = env::args().nth(1) {
-        if let Ok(n_bytes) = arg.parse() {
-            DataStore::anon(n_bytes)?
-        } else {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .open(arg)?;
-
-            DataStore::file(file)?
+    let options = Options::parse();
+    let (mut data_store, file_path) = match open(&options) {
+        Ok(opened) => opened,
+        Err(err) => {
+            eprintln!("bloxberg: {}", err);
+            process::exit(1);
         }
-    } else {
-        DataStore::anon(1024)?
+    };
+    let n_bytes = data_store.data().len();
+    let journal = match &options.journal {
+        Some(path) => Some(OpenOptions::new().append(true).create(true).open(path)?),
+        None => None,
+    };
+
+    let keymap = match config::path("keys").map(|path| Keymap::load(&path)) {
+        Some(Ok(keymap)) => keymap,
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        None => Keymap::default(),
+    };
+    let config = match config::path("config").map(|path| Config::load(&path)) {
+        Some(Ok(config)) => config,
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        None => Config::default(),
     };
 
     let stdout: MouseTerminal<_> = stdout().into_raw_mode()?.into();
     let writer = BufWriter::new(stdout);
     let (width, height) = termion::terminal_size()?;
     let mut editor = Editor::new(&mut data_store, writer, width as usize, height as usize);
+    editor.set_colors_enabled(options.color);
+    editor.set_status_format(config.status_format);
+    editor.set_prologues(config.prologues);
+    if let Some(journal) = journal {
+        editor.set_journal(journal);
+    }
     editor.init();
+    if let Some(cell) = options.default_cell {
+        editor.set_default_cell(cell);
+    }
+    if options.magic {
+        editor.apply_magic();
+    }
+    editor.set_pager(options.pager);
+    editor.draw();
+
+    if let (Some(path), true) = (&file_path, options.restore_cursor) {
+        if let Some(offset) = history::load_offset(path) {
+            if n_bytes > 0 {
+                editor.try_goto(min(offset, n_bytes - 1));
+                editor.draw();
+            }
+        }
+    }
+
+    if let Some(start) = &options.start {
+        editor.goto_start_arg(start);
+        editor.draw();
+    }
 
-    let stdin = stdin();
-    for evt in stdin.events() {
-        match evt? {
-            Event::Key(key) => handle_key(key, &mut editor),
-            Event::Mouse(me) => handle_mouse(me, &mut editor),
+    if let Some(path) = &options.commands {
+        editor.run_script(path);
+        editor.draw();
+    }
+
+    // input is read on a separate thread, so that keys can cancel long operations
+    let interrupt = Interrupt::default();
+    editor.set_interrupt(interrupt.clone());
+    let (sender, events) = mpsc::channel();
+    thread::spawn(move || {
+        for evt in stdin().events() {
+            if let Ok(Event::Key(_)) = evt {
+                if interrupt.cancel() {
+                    continue;
+                }
+            }
+            if sender.send(evt).is_err() {
+                break;
+            }
+        }
+    });
+
+    // pasted text is collected between the bracketed paste markers and handled at once
+    let mut paste: Option<String> = None;
+
+    // wake up periodically even without input, to pick up changes of the terminal size
+    while !editor.finished {
+        let evt = match events.recv_timeout(TICK) {
+            Ok(evt) => evt?,
+            Err(RecvTimeoutError::Timeout) => {
+                let (width, height) = termion::terminal_size()?;
+                if editor.resize(width as usize, height as usize) {
+                    editor.draw();
+                }
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match (evt, &mut paste) {
+            (Event::Unsupported(seq), None) if seq == PASTE_START => {
+                paste = Some(String::new());
+                continue;
+            }
+            (Event::Unsupported(seq), Some(text)) if seq == PASTE_END => {
+                editor.paste(text);
+                paste = None;
+            }
+            (Event::Key(Key::Char(c)), Some(text)) => {
+                text.push(c);
+                continue;
+            }
+            (_, Some(_)) => continue,
+            (Event::Key(key), None) => handle_key(key, &mut editor, &keymap),
+            (Event::Mouse(me), None) => handle_mouse(me, &mut editor),
             _ => {}
         }
 
@@ -99,5 +454,9 @@ fn main() -> Result<(), io::Error> {
         editor.draw();
     }
 
+    if let (Some(path), true) = (&file_path, options.restore_cursor) {
+        history::save_offset(path, editor.offset()).ok();
+    }
+
     Ok(())
 }
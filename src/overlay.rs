@@ -0,0 +1,79 @@
+use std::io::Write;
+
+use crate::terminal::{Color, Terminal};
+
+/// A scrollable list of results, each pointing to an offset in the buffer.
+pub struct Overlay {
+    title: String,
+    entries: Vec<(usize, String)>,
+    selected: usize,
+    scroll: usize,
+}
+
+impl Overlay {
+    pub fn new(title: String, entries: Vec<(usize, String)>) -> Self {
+        Overlay {
+            title,
+            entries,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn move_selection(&mut self, delta: isize, height: usize) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let selected = self.selected as isize + delta;
+        self.selected = selected.clamp(0, self.entries.len() as isize - 1) as usize;
+
+        let height = height.saturating_sub(1); // title row
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+    }
+
+    pub fn selected_offset(&self) -> Option<usize> {
+        self.entries.get(self.selected).map(|(offset, _)| *offset)
+    }
+
+    /// Draws the overlay into the `height` rows starting at terminal row `top`.
+    pub fn draw<W: Write>(&self, terminal: &Terminal<W>, top: usize, height: usize, width: usize) {
+        terminal.goto(1, top as u16);
+        write_color!(
+            terminal,
+            Color::Selected,
+            "{} ({} results)",
+            self.title,
+            self.entries.len()
+        );
+        terminal.clear_line();
+
+        for row in 1..height {
+            terminal.goto(1, (top + row) as u16);
+            let idx = self.scroll + row - 1;
+            if let Some((offset, text)) = self.entries.get(idx) {
+                let line = format!("{:#018x}  {}", offset, text);
+                let line: String = line.chars().take(width).collect();
+                if idx == self.selected {
+                    terminal.bg_color(Color::Selected);
+                    write!(terminal, "{}", line);
+                    terminal.reset_color();
+                } else {
+                    write!(terminal, "{}", line);
+                }
+            }
+            terminal.clear_line();
+        }
+    }
+
+    pub fn position(&self) -> (usize, usize) {
+        (self.selected + 1, self.entries.len())
+    }
+}
@@ -0,0 +1,186 @@
+use crate::cell::Cell;
+
+fn is_printable(b: u8) -> bool {
+    b.is_ascii_graphic() || b == b' '
+}
+
+/// Finds runs of at least `min_len` printable ASCII characters, like strings(1).
+pub fn ascii_strings(data: &[u8], min_len: usize) -> Vec<(usize, String)> {
+    let mut strings = vec![];
+    let mut start = 0;
+    for (i, &b) in data.iter().chain(&[0]).enumerate() {
+        if !is_printable(b) {
+            if i - start >= min_len {
+                strings.push((start, String::from_utf8_lossy(&data[start..i]).into_owned()));
+            }
+            start = i + 1;
+        }
+    }
+    strings
+}
+
+/// Finds runs of at least `min_len` printable ASCII characters encoded as UTF-16LE.
+pub fn utf16_strings(data: &[u8], min_len: usize) -> Vec<(usize, String)> {
+    let mut strings = vec![];
+    for parity in 0..2 {
+        let units = data.get(parity..).unwrap_or(&[]).chunks_exact(2);
+        let mut start = parity;
+        let mut s = String::new();
+        for (i, unit) in units.chain([[0, 0].as_ref()]).enumerate() {
+            let offset = parity + 2 * i;
+            if unit[1] == 0 && is_printable(unit[0]) {
+                s.push(unit[0] as char);
+            } else {
+                if s.len() >= min_len {
+                    strings.push((start, s.clone()));
+                }
+                s.clear();
+                start = offset + 2;
+            }
+        }
+    }
+    strings.sort_by_key(|(offset, _)| *offset);
+    strings
+}
+
+const BRACKETS: &[(u8, u8)] = &[(b'(', b')'), (b'[', b']'), (b'{', b'}')];
+
+pub fn is_bracket(b: u8) -> bool {
+    BRACKETS
+        .iter()
+        .any(|&(open, close)| b == open || b == close)
+}
+
+/// Finds the bracket matching the one at `offset`, skipping over nested pairs.
+pub fn matching_bracket(data: &[u8], offset: usize) -> Option<usize> {
+    let b = *data.get(offset)?;
+    let mut depth = 0;
+    if let Some(&(open, close)) = BRACKETS.iter().find(|(open, _)| *open == b) {
+        for (i, &b) in data.iter().enumerate().skip(offset) {
+            if b == open {
+                depth += 1;
+            } else if b == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+    } else if let Some(&(open, close)) = BRACKETS.iter().find(|(_, close)| *close == b) {
+        for (i, &b) in data[..=offset].iter().enumerate().rev() {
+            if b == close {
+                depth += 1;
+            } else if b == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Computes the Shannon entropy of `data` in bits per byte, from 0 to 8.
+pub fn entropy(data: &[u8]) -> f64 {
+    let mut counts = [0usize; 256];
+    for &b in data {
+        counts[b as usize] += 1;
+    }
+    let len = data.len() as f64;
+    counts
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| {
+            let p = n as f64 / len;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// Number of bytes scanned between two progress reports.
+pub const PROGRESS_INTERVAL: usize = 16 << 20;
+
+/// Returned by a scan that was cancelled from its progress callback.
+pub struct Cancelled;
+
+/// Finds the next offset after `start` (or before it, if `forward` is false) that is
+/// aligned to the width of `cell` and holds `value` when read with its byte order.
+///
+/// `progress` is called with the number of bytes scanned so far at regular intervals,
+/// and cancels the scan by returning false.
+pub fn find_value(
+    data: &[u8],
+    cell: Cell,
+    value: u128,
+    start: usize,
+    forward: bool,
+    progress: &mut dyn FnMut(usize) -> bool,
+) -> Result<Option<usize>, Cancelled> {
+    let n_bytes = cell.n_bytes();
+    let start = cell.width.align(start);
+    let offsets: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new((start + n_bytes..data.len()).step_by(n_bytes))
+    } else {
+        Box::new((0..start).step_by(n_bytes).rev())
+    };
+
+    let mut scanned = 0;
+    for offset in offsets {
+        if offset + n_bytes <= data.len() && cell.parse_value(&data[offset..]) == value {
+            return Ok(Some(offset));
+        }
+        scanned += n_bytes;
+        if scanned.is_multiple_of(PROGRESS_INTERVAL) && !progress(scanned) {
+            return Err(Cancelled);
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the next offset after `start` (or before it, if `forward` is false) whose byte
+/// differs from `pattern` repeated from the start of `data`.
+pub fn find_anomaly(
+    data: &[u8],
+    pattern: &[u8],
+    start: usize,
+    forward: bool,
+    progress: &mut dyn FnMut(usize) -> bool,
+) -> Result<Option<usize>, Cancelled> {
+    let offsets: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new(start + 1..data.len())
+    } else {
+        Box::new((0..start).rev())
+    };
+
+    for (i, offset) in offsets.enumerate() {
+        if data[offset] != pattern[offset % pattern.len()] {
+            return Ok(Some(offset));
+        }
+        let scanned = i + 1;
+        if scanned.is_multiple_of(PROGRESS_INTERVAL) && !progress(scanned) {
+            return Err(Cancelled);
+        }
+    }
+    Ok(None)
+}
+
+/// Finds the first offset after `start` at which one of `patterns` begins.
+pub fn find_patterns(
+    data: &[u8],
+    patterns: &[Vec<u8>],
+    start: usize,
+    progress: &mut dyn FnMut(usize) -> bool,
+) -> Result<Option<usize>, Cancelled> {
+    for offset in start + 1..data.len() {
+        let rest = &data[offset..];
+        if patterns.iter().any(|pattern| rest.starts_with(pattern)) {
+            return Ok(Some(offset));
+        }
+        let scanned = offset - start;
+        if scanned.is_multiple_of(PROGRESS_INTERVAL) && !progress(scanned) {
+            return Err(Cancelled);
+        }
+    }
+    Ok(None)
+}
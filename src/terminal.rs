@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::fmt;
 use std::io::Write;
 
@@ -9,6 +9,12 @@ pub enum Color {
     Null,
     Ascii,
     Cursor,
+    Visual,
+    Modified,
+    Mnemonic,
+    Register,
+    Immediate,
+    Locked,
 }
 
 impl Color {
@@ -19,31 +25,133 @@ impl Color {
             Color::Null => &termion::color::LightBlack,
             Color::Ascii => &termion::color::Yellow,
             Color::Cursor => &termion::color::LightGreen,
+            Color::Visual => &termion::color::Blue,
+            Color::Modified => &termion::color::LightRed,
+            Color::Mnemonic => &termion::color::LightCyan,
+            Color::Register => &termion::color::Green,
+            Color::Immediate => &termion::color::LightMagenta,
+            Color::Locked => &termion::color::Cyan,
         }
     }
 }
 
+/// Makes the terminal wrap pasted text in `PASTE_START` and `PASTE_END`.
+const ENABLE_BRACKETED_PASTE: &str = "\x1b[?2004h";
+const DISABLE_BRACKETED_PASTE: &str = "\x1b[?2004l";
+pub const PASTE_START: &[u8] = b"\x1b[200~";
+pub const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// Writes to the screen row by row. A row is collected from the `goto` to its first
+/// column until the next `goto` or `flush`, and only sent if it differs from what was
+/// last sent for that row.
 pub struct Terminal<W: Write> {
     writer: RefCell<W>,
+    colors_enabled: bool,
+    /// The output last sent for each row, indexed by row - 1.
+    rows: RefCell<Vec<Vec<u8>>>,
+    /// The row being collected and its output so far.
+    pending: RefCell<Option<(u16, Vec<u8>)>>,
+    /// The columns of the current row still to be dropped, and the columns written
+    /// since `clip_left`, including dropped ones.
+    clip: Cell<Option<(usize, usize)>>,
 }
 
 impl<W: Write> Terminal<W> {
     pub fn new(writer: W) -> Self {
         Terminal {
             writer: RefCell::new(writer),
+            colors_enabled: true,
+            rows: RefCell::new(vec![]),
+            pending: RefCell::new(None),
+            clip: Cell::new(None),
         }
     }
 
+    /// Turns color escapes on or off. Without them, everything is drawn in the
+    /// terminal's default colors.
+    pub fn set_colors_enabled(&mut self, enabled: bool) {
+        self.colors_enabled = enabled;
+    }
+
     pub fn write_fmt(&self, args: fmt::Arguments) {
-        self.writer.borrow_mut().write_fmt(args).unwrap();
+        if let Some((skip, cols)) = self.clip.get() {
+            let (text, n_cols) = clip_text(&fmt::format(args), skip);
+            self.clip
+                .set(Some((skip.saturating_sub(n_cols), cols + n_cols)));
+            self.write_str(&text);
+            return;
+        }
+        match &mut *self.pending.borrow_mut() {
+            Some((_, output)) => output.write_fmt(args).unwrap(),
+            None => self.writer.borrow_mut().write_fmt(args).unwrap(),
+        }
+    }
+
+    fn write_str(&self, text: &str) {
+        match &mut *self.pending.borrow_mut() {
+            Some((_, output)) => output.extend_from_slice(text.as_bytes()),
+            None => self.writer.borrow_mut().write_all(text.as_bytes()).unwrap(),
+        }
+    }
+
+    /// Drops the next `n` columns written to the current row, which scrolls the rest of
+    /// it to the left. Escape sequences are kept, so colors still apply.
+    pub fn clip_left(&self, n: usize) {
+        self.clip.set(Some((n, 0)));
+    }
+
+    /// Returns the columns written to the current row since `clip_left`, including
+    /// those that were dropped.
+    pub fn clipped_cols(&self) -> usize {
+        self.clip.get().map_or(0, |(_, cols)| cols)
     }
 
     pub fn flush(&self) {
+        self.end_row();
         self.writer.borrow_mut().flush().unwrap();
     }
 
+    /// Sends the collected row, unless it is unchanged.
+    fn end_row(&self) {
+        let (y, output) = match self.pending.borrow_mut().take() {
+            Some(pending) => pending,
+            None => return,
+        };
+        let mut rows = self.rows.borrow_mut();
+        let idx = y as usize - 1;
+        if idx >= rows.len() {
+            rows.resize(idx + 1, vec![]);
+        }
+        if rows[idx] != output {
+            let mut writer = self.writer.borrow_mut();
+            write!(writer, "{}", termion::cursor::Goto(1, y)).unwrap();
+            if self.colors_enabled {
+                // rows in between may have been skipped, so the colors left over from
+                // the last row sent need not be those the row was drawn with
+                write!(
+                    writer,
+                    "{}{}",
+                    termion::color::Bg(termion::color::Reset),
+                    termion::color::Fg(termion::color::Reset)
+                )
+                .unwrap();
+            }
+            writer.write_all(&output).unwrap();
+            rows[idx] = output;
+        }
+    }
+
+    /// Clears the screen, so that every row is sent again.
     pub fn init(&self) {
-        write!(self, "{}{}", termion::clear::All, termion::cursor::Hide);
+        self.pending.borrow_mut().take();
+        self.rows.borrow_mut().clear();
+        write!(
+            self,
+            "{}{}{}",
+            termion::clear::All,
+            termion::cursor::Hide,
+            ENABLE_BRACKETED_PASTE
+        );
     }
 
     pub fn clear_line(&self) {
@@ -51,18 +159,33 @@ impl<W: Write> Terminal<W> {
     }
 
     pub fn goto(&self, x: u16, y: u16) {
-        write!(self, "{}", termion::cursor::Goto(x, y));
+        self.end_row();
+        self.clip.set(None);
+        if x == 1 {
+            *self.pending.borrow_mut() = Some((y, vec![]));
+        } else {
+            write!(self, "{}", termion::cursor::Goto(x, y));
+        }
     }
 
     pub fn fg_color(&self, color: Color) {
+        if !self.colors_enabled {
+            return;
+        }
         write!(self, "{}", termion::color::Fg(color.termion()));
     }
 
     pub fn bg_color(&self, color: Color) {
+        if !self.colors_enabled {
+            return;
+        }
         write!(self, "{}", termion::color::Bg(color.termion()));
     }
 
     pub fn reset_color(&self) {
+        if !self.colors_enabled {
+            return;
+        }
         write!(
             self,
             "{}{}",
@@ -72,6 +195,10 @@ impl<W: Write> Terminal<W> {
     }
 
     pub fn write_color(&self, color: Color, args: fmt::Arguments) {
+        if !self.colors_enabled {
+            self.write_fmt(args);
+            return;
+        }
         self.fg_color(color);
         self.write_fmt(args);
         write!(self, "{}", termion::color::Fg(termion::color::Reset));
@@ -80,9 +207,11 @@ impl<W: Write> Terminal<W> {
 
 impl<W: Write> Drop for Terminal<W> {
     fn drop(&mut self) {
+        self.pending.borrow_mut().take();
         write!(
             self,
-            "{}{}{}",
+            "{}{}{}{}",
+            DISABLE_BRACKETED_PASTE,
             termion::clear::All,
             termion::cursor::Goto(1, 1),
             termion::cursor::Show
@@ -91,6 +220,29 @@ impl<W: Write> Drop for Terminal<W> {
     }
 }
 
+/// Drops the first `skip` visible characters of `text`, keeping escape sequences.
+/// Returns the remaining text and the number of visible characters in `text`.
+fn clip_text(text: &str, skip: usize) -> (String, usize) {
+    let mut clipped = String::with_capacity(text.len());
+    let mut n_cols = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        if c == '\x1b' {
+            in_escape = true;
+        } else if in_escape {
+            // a CSI sequence ends with a byte in @..~, after its opening [
+            in_escape = c == '[' || !('@'..='~').contains(&c);
+        } else {
+            n_cols += 1;
+            if n_cols <= skip {
+                continue;
+            }
+        }
+        clipped.push(c);
+    }
+    (clipped, n_cols)
+}
+
 macro_rules! write_color {
     ($dst:expr, $col:expr, $($arg:tt)*) => ($dst.write_color($col, format_args!($($arg)*)))
 }
@@ -1,4 +1,5 @@
 use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::ops::Range;
 
 pub fn cmp_range<T: Ord>(x: T, r: Range<T>) -> Ordering {
@@ -10,3 +11,99 @@ pub fn cmp_range<T: Ord>(x: T, r: Range<T>) -> Ordering {
         Ordering::Equal
     }
 }
+
+pub fn parse_hex_bytes(s: &str) -> Option<Vec<u8>> {
+    let digits = s
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect())
+}
+
+/// Parses an address with an optional `0x`, `0o` or `0b` prefix or a `d` suffix for
+/// decimal. Addresses without either are hexadecimal, as is a `d`-suffixed address
+/// that is not valid decimal, so `12ad` is `0x12ad`. An address such as `1d` is valid
+/// either way and is read as decimal; write `0x1d` for the hexadecimal one.
+pub fn parse_addr(s: &str) -> Option<u128> {
+    let parse = |digits: &str, radix| {
+        // from_str_radix accepts a leading sign, which makes no sense for an address
+        if digits.starts_with('+') {
+            return None;
+        }
+        u128::from_str_radix(digits, radix).ok()
+    };
+    if let Some(digits) = s.strip_prefix("0x") {
+        parse(digits, 16)
+    } else if let Some(digits) = s.strip_prefix("0o") {
+        parse(digits, 8)
+    } else if let Some(digits) = s.strip_prefix("0b") {
+        parse(digits, 2)
+    } else if let Some(digits) = s.strip_suffix('d') {
+        parse(digits, 10).or_else(|| parse(s, 16))
+    } else {
+        parse(s, 16)
+    }
+}
+
+/// Converts a parsed address to an offset. An address past `usize::MAX` becomes
+/// `usize::MAX`, which is out of range like the address itself.
+pub fn clamp_to_usize(addr: u128) -> usize {
+    usize::try_from(addr).unwrap_or(usize::MAX)
+}
+
+/// Parses the decimal count argument of a command, which may be missing.
+pub fn parse_count(arg: Option<&str>) -> Option<usize> {
+    arg?.trim().parse().ok()
+}
+
+/// Draws a horizontal bar of block characters that fills `fraction` of `width` columns,
+/// with eighth blocks for the remainder.
+pub fn bar(fraction: f64, width: usize) -> String {
+    const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    let eighths = (fraction * (width * 8) as f64).round() as usize;
+    let mut bar = "█".repeat(eighths / 8);
+    if !eighths.is_multiple_of(8) {
+        bar.push(EIGHTHS[eighths % 8]);
+    }
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_addr_with_d_suffix() {
+        assert_eq!(parse_addr("100d"), Some(100));
+        assert_eq!(parse_addr("1d"), Some(1));
+        assert_eq!(parse_addr("12ad"), Some(0x12ad));
+        assert_eq!(parse_addr("fd"), Some(0xfd));
+        assert_eq!(parse_addr("d"), Some(0xd));
+        assert_eq!(parse_addr("+1d"), None);
+    }
+
+    #[test]
+    fn clamp_to_usize_saturates() {
+        assert_eq!(clamp_to_usize(0x10), 0x10);
+        assert_eq!(clamp_to_usize(u128::MAX), usize::MAX);
+    }
+
+    #[test]
+    fn parse_count_without_argument() {
+        assert_eq!(parse_count(None), None);
+        assert_eq!(parse_count(Some("")), None);
+    }
+
+    #[test]
+    fn parse_count_with_garbage() {
+        assert_eq!(parse_count(Some("ten")), None);
+        assert_eq!(parse_count(Some("-3")), None);
+        assert_eq!(parse_count(Some("12x")), None);
+        assert_eq!(parse_count(Some("12")), Some(12));
+    }
+}